@@ -0,0 +1,477 @@
+use crate::config::EndpointConfig;
+use crate::discovery::Discovery;
+use crate::events::{Event, EventKind};
+use crate::model_manager::{ModelManager, PullProgress};
+use crate::registration::{Lease, RegisteredNode, Registration, Registry};
+use crate::server::{AppError, AppState};
+use crate::{CircuitState, Endpoint, EndpointUpdate, HealthState, LoadBalancerError};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::sse::{self, KeepAlive, Sse},
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::future::join_all;
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+#[derive(Deserialize)]
+pub struct EndpointRef {
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub url: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DrainStatus {
+    pub url: String,
+    pub draining: bool,
+    pub current_connections: u32,
+    /// Draining and no requests left in flight, so the endpoint can be taken down.
+    pub drained: bool,
+}
+
+/// Everything the admin API knows about an endpoint.
+#[derive(Serialize, Deserialize)]
+pub struct EndpointStatus {
+    pub url: String,
+    pub labels: BTreeMap<String, String>,
+    pub weight: u32,
+    pub effective_weight: f64,
+    pub max_connections: u32,
+    pub current_connections: u32,
+    pub healthy: bool,
+    pub state: HealthState,
+    pub state_since: Option<u64>,
+    pub state_reason: Option<String>,
+    pub draining: bool,
+    pub ejected: bool,
+    pub circuit: CircuitState,
+    pub canary_failures: BTreeMap<String, String>,
+}
+
+impl EndpointStatus {
+    pub fn of(endpoint: &Endpoint) -> Self {
+        let transition = endpoint.last_health_transition();
+        Self {
+            url: endpoint.url.clone(),
+            labels: endpoint.labels.clone(),
+            weight: endpoint.weight,
+            effective_weight: endpoint.effective_weight(),
+            max_connections: endpoint.max_connections,
+            current_connections: endpoint.get_connections(),
+            healthy: endpoint.is_healthy(),
+            state: endpoint.health_state(),
+            state_since: transition.as_ref().and_then(|t| {
+                t.at.duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|since| since.as_secs())
+            }),
+            state_reason: transition.map(|t| t.reason),
+            draining: endpoint.is_draining(),
+            ejected: endpoint.is_ejected(),
+            circuit: endpoint.circuit_state(),
+            canary_failures: endpoint.canary_failures(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EndpointPatch {
+    pub url: String,
+    #[serde(flatten)]
+    pub update: EndpointUpdate,
+}
+
+#[derive(Serialize)]
+pub struct CheckResult {
+    #[serde(flatten)]
+    pub endpoint: EndpointStatus,
+    /// Why the check failed, if it did.
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PullRequest {
+    pub url: String,
+    /// Defaults to `required_model`.
+    pub model: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PullStatus {
+    pub url: String,
+    pub model: String,
+    /// Latest progress reported by Ollama.
+    #[serde(flatten)]
+    pub progress: PullProgress,
+    pub done: bool,
+    pub error: Option<String>,
+}
+
+/// Models installed on an endpoint, or why they could not be listed.
+#[derive(Serialize, Deserialize)]
+pub struct EndpointModels {
+    pub url: String,
+    pub models: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Model pulls started through the admin API, by endpoint URL and model.
+/// Finished pulls stay listed until the same pull is started again.
+#[derive(Default)]
+pub struct ModelPulls {
+    pulls: Mutex<BTreeMap<(String, String), PullStatus>>,
+}
+
+impl ModelPulls {
+    fn update(&self, key: &(String, String), update: impl FnOnce(&mut PullStatus)) {
+        if let Some(status) = self.pulls.lock().unwrap().get_mut(key) {
+            update(status);
+        }
+    }
+}
+
+/// The admin API, served on `server.admin_listen`.
+pub fn router(state: Arc<AppState>) -> Router {
+    routes()
+        .layer(tower_http::trace::TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+/// Just the node-facing registration calls, served on `registration.listen`.
+/// They are part of the admin API as well.
+pub fn registration_router(state: Arc<AppState>) -> Router {
+    registration_routes()
+        .layer(tower_http::trace::TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+fn registration_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/endpoints/register", post(register))
+        .route("/admin/endpoints/heartbeat", post(heartbeat))
+        .route("/admin/endpoints/deregister", post(deregister))
+}
+
+fn routes() -> Router<Arc<AppState>> {
+    registration_routes()
+        .route(
+            "/admin/endpoints",
+            get(list_endpoints)
+                .post(add_endpoint)
+                .patch(update_endpoint)
+                .delete(remove_endpoint),
+        )
+        .route("/admin/endpoints/check", post(check))
+        .route("/admin/endpoints/pull", post(pull))
+        .route("/admin/endpoints/pulls", get(pulls))
+        .route("/admin/models", get(models))
+        .route("/admin/endpoints/drain", get(drain_status).post(drain))
+        .route("/admin/endpoints/undrain", post(undrain))
+        .route("/admin/endpoints/history", get(history))
+        .route("/admin/endpoints/registered", get(registered))
+        .route("/admin/events", get(events))
+}
+
+fn drain_status_of(state: &AppState, url: &str) -> Result<DrainStatus, AppError> {
+    let endpoint = state.load_balancer.find_endpoint(url)?;
+    let draining = endpoint.is_draining();
+    let current_connections = endpoint.get_connections();
+    Ok(DrainStatus {
+        url: endpoint.url.clone(),
+        draining,
+        current_connections,
+        drained: draining && current_connections == 0,
+    })
+}
+
+async fn drain_status(
+    State(state): State<Arc<AppState>>,
+    Query(endpoint): Query<EndpointRef>,
+) -> Result<Json<DrainStatus>, AppError> {
+    Ok(Json(drain_status_of(&state, &endpoint.url)?))
+}
+
+async fn drain(
+    State(state): State<Arc<AppState>>,
+    Json(endpoint): Json<EndpointRef>,
+) -> Result<Json<DrainStatus>, AppError> {
+    state.load_balancer.find_endpoint(&endpoint.url)?.drain();
+    Ok(Json(drain_status_of(&state, &endpoint.url)?))
+}
+
+async fn undrain(
+    State(state): State<Arc<AppState>>,
+    Json(endpoint): Json<EndpointRef>,
+) -> Result<Json<DrainStatus>, AppError> {
+    state.load_balancer.find_endpoint(&endpoint.url)?.undrain();
+    Ok(Json(drain_status_of(&state, &endpoint.url)?))
+}
+
+async fn history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<Event>>, AppError> {
+    let endpoint = state.load_balancer.find_endpoint(&query.url)?;
+    Ok(Json(endpoint.history(query.limit.unwrap_or(usize::MAX))))
+}
+
+async fn list_endpoints(State(state): State<Arc<AppState>>) -> Json<Vec<EndpointStatus>> {
+    Json(
+        state
+            .load_balancer
+            .endpoints()
+            .iter()
+            .map(EndpointStatus::of)
+            .collect(),
+    )
+}
+
+async fn add_endpoint(
+    State(state): State<Arc<AppState>>,
+    Json(endpoint): Json<EndpointConfig>,
+) -> Result<(StatusCode, Json<EndpointStatus>), AppError> {
+    if endpoint.dns.is_some() {
+        return Err(LoadBalancerError::BadRequest(
+            "dns entries are only supported in the config file".to_string(),
+        )
+        .into());
+    }
+    let url = endpoint.url.clone();
+    state.load_balancer.add_endpoint(endpoint)?;
+    info!("Admin API added endpoint {}", url);
+    let endpoint = state.load_balancer.find_endpoint(&url)?;
+    Ok((StatusCode::CREATED, Json(EndpointStatus::of(&endpoint))))
+}
+
+async fn update_endpoint(
+    State(state): State<Arc<AppState>>,
+    Json(patch): Json<EndpointPatch>,
+) -> Result<Json<EndpointStatus>, AppError> {
+    state
+        .load_balancer
+        .update_endpoint(&patch.url, patch.update.clone())?;
+    let endpoint = state.load_balancer.find_endpoint(&patch.url)?;
+    info!(
+        "Admin API set {} to weight {}, max_connections {}",
+        endpoint.url, endpoint.weight, endpoint.max_connections
+    );
+    Ok(Json(EndpointStatus::of(&endpoint)))
+}
+
+async fn remove_endpoint(
+    State(state): State<Arc<AppState>>,
+    Query(endpoint): Query<EndpointRef>,
+) -> Result<StatusCode, AppError> {
+    let changes = state.load_balancer.remove_endpoint(&endpoint.url)?;
+    info!("Admin API removed endpoint {:?}", changes.removed);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Probes an endpoint now rather than waiting for its next scheduled check.
+async fn check(
+    State(state): State<Arc<AppState>>,
+    Json(endpoint): Json<EndpointRef>,
+) -> Result<Json<CheckResult>, AppError> {
+    let error = match state.load_balancer.check_endpoint(&endpoint.url).await {
+        Ok(true) => None,
+        Ok(false) => Some("reported unhealthy".to_string()),
+        Err(LoadBalancerError::EndpointNotFound(url)) => {
+            return Err(LoadBalancerError::EndpointNotFound(url).into())
+        }
+        Err(e) => Some(e.to_string()),
+    };
+    let endpoint = state.load_balancer.find_endpoint(&endpoint.url)?;
+    info!(
+        "Admin API checked {}: {}",
+        endpoint.url,
+        endpoint.health_state().as_str()
+    );
+    Ok(Json(CheckResult {
+        endpoint: EndpointStatus::of(&endpoint),
+        error,
+    }))
+}
+
+/// Starts pulling a model onto an endpoint in the background; follow it
+/// through `/admin/endpoints/pulls`. A pull already running is not restarted.
+async fn pull(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<PullRequest>,
+) -> Result<(StatusCode, Json<PullStatus>), AppError> {
+    let endpoint = state.load_balancer.find_endpoint(&request.url)?;
+    let model = request
+        .model
+        .unwrap_or_else(|| state.required_model.clone());
+    let key = (endpoint.url.clone(), model.clone());
+
+    let status = PullStatus {
+        url: endpoint.url.clone(),
+        model: model.clone(),
+        progress: PullProgress {
+            status: "starting".to_string(),
+            ..Default::default()
+        },
+        done: false,
+        error: None,
+    };
+    {
+        let mut pulls = state.pulls.pulls.lock().unwrap();
+        if let Some(running) = pulls.get(&key).filter(|pull| !pull.done) {
+            return Ok((StatusCode::OK, Json(running.clone())));
+        }
+        pulls.insert(key.clone(), status.clone());
+    }
+    info!("Admin API started pulling {} on {}", model, endpoint.url);
+
+    let pulls = state.clone();
+    tokio::spawn(async move {
+        let result = ModelManager::new()
+            .pull_model_with_progress(&endpoint, &model, |progress| {
+                pulls
+                    .pulls
+                    .update(&key, |status| status.progress = progress)
+            })
+            .await;
+        match &result {
+            Ok(()) => {
+                endpoint.record_event(EventKind::ModelPulled {
+                    model: model.clone(),
+                });
+            }
+            Err(e) => warn!("Pulling {} on {} failed: {}", model, endpoint.url, e),
+        }
+        pulls.pulls.update(&key, |status| {
+            status.done = true;
+            status.error = result.err().map(|e| e.to_string());
+        });
+    });
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+async fn pulls(State(state): State<Arc<AppState>>) -> Json<Vec<PullStatus>> {
+    Json(
+        state
+            .pulls
+            .pulls
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Asks every endpoint for its installed models.
+async fn models(State(state): State<Arc<AppState>>) -> Json<Vec<EndpointModels>> {
+    let model_manager = ModelManager::new();
+    let endpoints = state.load_balancer.endpoints();
+    Json(
+        join_all(endpoints.iter().map(|endpoint| async {
+            let (models, error) = match model_manager.list_models(endpoint).await {
+                Ok(models) => (models, None),
+                Err(e) => (Vec::new(), Some(e.to_string())),
+            };
+            EndpointModels {
+                url: endpoint.url.clone(),
+                models,
+                error,
+            }
+        }))
+        .await,
+    )
+}
+
+fn registry(state: &AppState) -> Result<&Registry, AppError> {
+    Ok(state
+        .registry
+        .as_deref()
+        .ok_or(LoadBalancerError::RegistrationDisabled)?)
+}
+
+/// The registry, if the request carries the registration token.
+fn authorized_registry<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+) -> Result<&'a Registry, AppError> {
+    let registry = registry(state)?;
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    registry.authorize(token)?;
+    Ok(registry)
+}
+
+/// Registers or re-registers a node; it is routed to once healthy.
+async fn register(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(registration): Json<Registration>,
+) -> Result<Json<Lease>, AppError> {
+    let registry = authorized_registry(&state, &headers)?;
+    let lease = registry.register(registration)?;
+    registry.refresh(&state.load_balancer).await;
+    Ok(Json(lease))
+}
+
+async fn heartbeat(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(endpoint): Json<EndpointRef>,
+) -> Result<Json<Lease>, AppError> {
+    Ok(Json(
+        authorized_registry(&state, &headers)?.heartbeat(&endpoint.url)?,
+    ))
+}
+
+async fn deregister(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(endpoint): Json<EndpointRef>,
+) -> Result<(), AppError> {
+    let registry = authorized_registry(&state, &headers)?;
+    registry.deregister(&endpoint.url)?;
+    registry.refresh(&state.load_balancer).await;
+    Ok(())
+}
+
+async fn registered(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<RegisteredNode>>, AppError> {
+    Ok(Json(registry(&state)?.nodes()))
+}
+
+/// Server-Sent Events stream of fleet events, named after their `type`.
+async fn events(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let receiver = state.load_balancer.subscribe_events();
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => sse::Event::default()
+                .event(event.kind.name())
+                .json_data(&event)
+                .unwrap_or_default(),
+            // A slow subscriber is told how much it missed rather than cut off
+            Err(RecvError::Lagged(missed)) => sse::Event::default()
+                .event("lagged")
+                .data(missed.to_string()),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
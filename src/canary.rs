@@ -0,0 +1,461 @@
+use crate::endpoint::Endpoint;
+use futures_util::future::join_all;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+use tracing::{info, warn};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CanaryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+    /// Sent with `temperature: 0` so a healthy endpoint answers the same every time.
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+    #[serde(default)]
+    pub checks: Vec<CanaryCheck>,
+}
+
+impl Default for CanaryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: default_interval_seconds(),
+            timeout_seconds: default_timeout_seconds(),
+            seed: default_seed(),
+            checks: Vec::new(),
+        }
+    }
+}
+
+fn default_interval_seconds() -> u64 {
+    300
+}
+
+fn default_timeout_seconds() -> u64 {
+    60
+}
+
+fn default_seed() -> u64 {
+    42
+}
+
+fn default_min_similarity() -> f64 {
+    0.99
+}
+
+/// A prompt run against every endpoint, and what a healthy answer looks like.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CanaryCheck {
+    pub model: String,
+    /// Generation prompt, or the embedding input for `embedding` checks.
+    pub prompt: String,
+    #[serde(flatten)]
+    pub expect: CanaryExpectation,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum CanaryExpectation {
+    /// The answer must equal this text, ignoring surrounding whitespace.
+    Text(String),
+    Regex(String),
+    /// The embedding must stay within `min_similarity` (cosine) of a known-good
+    /// reference, given either as a file holding a JSON array or inline.
+    Embedding {
+        #[serde(default)]
+        reference_file: Option<String>,
+        #[serde(default)]
+        reference: Option<Vec<f64>>,
+        #[serde(default = "default_min_similarity")]
+        min_similarity: f64,
+    },
+}
+
+enum Expectation {
+    Text(String),
+    Regex(Regex),
+    Embedding {
+        reference: Vec<f64>,
+        min_similarity: f64,
+    },
+}
+
+struct Check {
+    model: String,
+    prompt: String,
+    expect: Expectation,
+}
+
+/// Why a check did not pass. Only a wrong answer counts against the endpoint;
+/// one that could not be asked is left for the health checks to judge.
+enum CheckError {
+    Unreachable(String),
+    Mismatch(String),
+}
+
+/// Periodically runs the configured prompts against every endpoint and marks
+/// endpoints whose output has drifted as failing for that model.
+pub struct Canary {
+    client: reqwest::Client,
+    config: CanaryConfig,
+    checks: Vec<Check>,
+    stop: watch::Sender<bool>,
+}
+
+impl Canary {
+    pub fn new(config: CanaryConfig) -> crate::Result<Self> {
+        let checks = config
+            .checks
+            .iter()
+            .map(|check| {
+                let expect = match &check.expect {
+                    CanaryExpectation::Text(text) => Expectation::Text(text.trim().to_string()),
+                    CanaryExpectation::Regex(pattern) => {
+                        Expectation::Regex(Regex::new(pattern).map_err(|e| {
+                            crate::LoadBalancerError::ConfigError(format!(
+                                "invalid canary regex {:?}: {}",
+                                pattern, e
+                            ))
+                        })?)
+                    }
+                    CanaryExpectation::Embedding {
+                        reference_file,
+                        reference,
+                        min_similarity,
+                    } => Expectation::Embedding {
+                        reference: match (reference_file, reference) {
+                            (Some(path), None) => load_reference(path)?,
+                            (None, Some(reference)) => reference.clone(),
+                            _ => {
+                                return Err(crate::LoadBalancerError::ConfigError(format!(
+                                    "canary check for {} needs exactly one of \
+                                     reference_file and reference",
+                                    check.model
+                                )))
+                            }
+                        },
+                        min_similarity: *min_similarity,
+                    },
+                };
+                Ok(Check {
+                    model: check.model.clone(),
+                    prompt: check.prompt.clone(),
+                    expect,
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Ok(Self {
+            client,
+            config,
+            checks,
+            stop: watch::channel(false).0,
+        })
+    }
+
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    /// Runs until stopped, checking whichever endpoints are configured at each round.
+    pub async fn run(&self, endpoints: watch::Receiver<Arc<Vec<Endpoint>>>) {
+        info!(
+            "Starting {} canary checks every {} seconds",
+            self.checks.len(),
+            self.config.interval_seconds
+        );
+        let mut ticker = time::interval(Duration::from_secs(self.config.interval_seconds));
+        let mut stop = self.stop.subscribe();
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = stop.wait_for(|stopped| *stopped) => break,
+            }
+            let current = endpoints.borrow().clone();
+            join_all(current.iter().map(|endpoint| self.check_endpoint(endpoint))).await;
+        }
+
+        info!("Canary checks stopped");
+    }
+
+    async fn check_endpoint(&self, endpoint: &Endpoint) {
+        // Checks on one endpoint run one at a time so they don't compete for its
+        // GPU; a model fails if any of its checks does
+        let mut results: BTreeMap<&str, Option<Result<(), String>>> = BTreeMap::new();
+        for check in &self.checks {
+            let result = match self.run_check(endpoint, check).await {
+                Ok(()) => Some(Ok(())),
+                Err(CheckError::Mismatch(reason)) => Some(Err(reason)),
+                Err(CheckError::Unreachable(reason)) => {
+                    warn!(
+                        "Canary for {} on {} could not run, keeping its last result: {}",
+                        check.model, endpoint.url, reason
+                    );
+                    None
+                }
+            };
+            let entry = results.entry(&check.model).or_insert(Some(Ok(())));
+            // A mismatch outweighs a check that could not run, which outweighs a pass
+            match (&*entry, &result) {
+                (Some(Err(_)), _) | (None, Some(Ok(()))) => {}
+                _ => *entry = result,
+            }
+        }
+        for (model, result) in results {
+            if let Some(result) = result {
+                endpoint.record_canary(model, result);
+            }
+        }
+    }
+
+    async fn run_check(&self, endpoint: &Endpoint, check: &Check) -> Result<(), CheckError> {
+        match &check.expect {
+            Expectation::Text(expected) => {
+                let answer = self.generate(endpoint, check).await?;
+                if answer.trim() == expected {
+                    Ok(())
+                } else {
+                    Err(CheckError::Mismatch(format!(
+                        "expected {:?}, got {:?}",
+                        expected,
+                        answer.trim()
+                    )))
+                }
+            }
+            Expectation::Regex(regex) => {
+                let answer = self.generate(endpoint, check).await?;
+                if regex.is_match(&answer) {
+                    Ok(())
+                } else {
+                    Err(CheckError::Mismatch(format!(
+                        "{:?} does not match /{}/",
+                        answer.trim(),
+                        regex
+                    )))
+                }
+            }
+            Expectation::Embedding {
+                reference,
+                min_similarity,
+            } => {
+                let embedding = self.embed(endpoint, check).await?;
+                compare_embedding(reference, &embedding, *min_similarity)
+                    .map_err(CheckError::Mismatch)
+            }
+        }
+    }
+
+    async fn generate(&self, endpoint: &Endpoint, check: &Check) -> Result<String, CheckError> {
+        let response: serde_json::Value = self
+            .post(
+                endpoint,
+                "/api/generate",
+                serde_json::json!({
+                    "model": check.model,
+                    "prompt": check.prompt,
+                    "stream": false,
+                    "options": { "temperature": 0, "seed": self.config.seed },
+                }),
+            )
+            .await?;
+        response
+            .get("response")
+            .and_then(|r| r.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| CheckError::Mismatch("generation response has no text".to_string()))
+    }
+
+    async fn embed(&self, endpoint: &Endpoint, check: &Check) -> Result<Vec<f64>, CheckError> {
+        let response: serde_json::Value = self
+            .post(
+                endpoint,
+                "/api/embed",
+                serde_json::json!({
+                    "model": check.model,
+                    "input": check.prompt,
+                    "options": { "temperature": 0, "seed": self.config.seed },
+                }),
+            )
+            .await?;
+        response
+            .get("embeddings")
+            .and_then(|e| e.get(0))
+            .and_then(|e| serde_json::from_value(e.clone()).ok())
+            .ok_or_else(|| CheckError::Mismatch("embedding response has no vector".to_string()))
+    }
+
+    async fn post(
+        &self,
+        endpoint: &Endpoint,
+        path: &str,
+        body: serde_json::Value,
+    ) -> Result<serde_json::Value, CheckError> {
+        let response = self
+            .client
+            .post(format!("{}{}", endpoint.url, path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| CheckError::Unreachable(format!("canary request failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(CheckError::Unreachable(format!(
+                "canary request returned {}",
+                response.status()
+            )));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| CheckError::Unreachable(format!("unreadable canary response: {}", e)))
+    }
+}
+
+fn load_reference(path: &str) -> crate::Result<Vec<f64>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        crate::LoadBalancerError::ConfigError(format!(
+            "cannot read canary reference {}: {}",
+            path, e
+        ))
+    })?;
+    serde_json::from_str(&contents).map_err(|e| {
+        crate::LoadBalancerError::ConfigError(format!("invalid canary reference {}: {}", path, e))
+    })
+}
+
+fn compare_embedding(
+    reference: &[f64],
+    embedding: &[f64],
+    min_similarity: f64,
+) -> Result<(), String> {
+    if reference.len() != embedding.len() {
+        return Err(format!(
+            "embedding has {} dimensions, the reference {}",
+            embedding.len(),
+            reference.len()
+        ));
+    }
+    let similarity = cosine_similarity(reference, embedding);
+    if similarity >= min_similarity {
+        Ok(())
+    } else {
+        Err(format!(
+            "embedding similarity {:.4} is below {}",
+            similarity, min_similarity
+        ))
+    }
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_similarity_of_vectors() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-9);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-9);
+        assert!((cosine_similarity(&[1.0, 1.0], &[-1.0, -1.0]) + 1.0).abs() < 1e-9);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn embeddings_are_compared_against_the_reference() {
+        let reference = [0.1, 0.2, 0.3];
+        assert!(compare_embedding(&reference, &[0.1, 0.2, 0.3], 0.99).is_ok());
+        assert!(compare_embedding(&reference, &[0.11, 0.2, 0.29], 0.99).is_ok());
+        assert!(compare_embedding(&reference, &[0.1, -0.9, 0.3], 0.99).is_err());
+        let error = compare_embedding(&reference, &[0.1, 0.2], 0.99).unwrap_err();
+        assert!(error.contains("dimensions"), "{}", error);
+    }
+
+    #[test]
+    fn embedding_checks_need_exactly_one_reference() {
+        let config = |expect: &str| -> CanaryConfig {
+            serde_yaml::from_str(&format!(
+                "checks:\n  - model: m\n    prompt: p\n    embedding: {}\n",
+                expect
+            ))
+            .unwrap()
+        };
+        assert!(Canary::new(config("{reference: [0.1, 0.2]}")).is_ok());
+        assert!(Canary::new(config("{min_similarity: 0.9}")).is_err());
+        assert!(Canary::new(config(
+            "{reference: [0.1], reference_file: /nonexistent.json}"
+        ))
+        .is_err());
+        assert!(Canary::new(config("{reference_file: /nonexistent.json}")).is_err());
+    }
+
+    /// A canary checking that `m` answers "4", against an endpoint that
+    /// answers `answer` or is not listening at all.
+    async fn check(answer: Option<&'static str>, endpoint_failing: bool) -> Endpoint {
+        use axum::{routing::post, Json, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint::new(format!("http://{}", listener.local_addr().unwrap()), 1, 10);
+        match answer {
+            Some(answer) => {
+                let app = Router::new().route(
+                    "/api/generate",
+                    post(move || async move { Json(serde_json::json!({ "response": answer })) }),
+                );
+                tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            }
+            None => drop(listener),
+        }
+        if endpoint_failing {
+            endpoint.record_canary("m", Err("earlier mismatch".to_string()));
+        }
+
+        let config: CanaryConfig =
+            serde_yaml::from_str("checks:\n  - model: m\n    prompt: p\n    text: \"4\"\n")
+                .unwrap();
+        Canary::new(config).unwrap().check_endpoint(&endpoint).await;
+        endpoint
+    }
+
+    #[tokio::test]
+    async fn only_mismatches_fail_the_model() {
+        assert!(!check(Some("4"), false).await.is_model_failing("m"));
+        assert!(!check(Some("4"), true).await.is_model_failing("m"));
+
+        let endpoint = check(Some("5"), false).await;
+        assert_eq!(endpoint.canary_failures()["m"], "expected \"4\", got \"5\"");
+    }
+
+    #[tokio::test]
+    async fn unreachable_endpoints_keep_their_last_result() {
+        assert!(!check(None, false).await.is_model_failing("m"));
+        let endpoint = check(None, true).await;
+        assert_eq!(endpoint.canary_failures()["m"], "earlier mismatch");
+    }
+}
@@ -0,0 +1,317 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Consecutive failed requests or health probes that trip a closed circuit.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects traffic before letting trial requests through.
+    #[serde(default = "default_open_seconds")]
+    pub open_seconds: u64,
+    /// Trial requests admitted while half-open.
+    #[serde(default = "default_half_open_max_requests")]
+    pub half_open_max_requests: u32,
+    /// Successful trials needed to close the circuit again.
+    #[serde(default = "default_success_threshold")]
+    pub success_threshold: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            failure_threshold: default_failure_threshold(),
+            open_seconds: default_open_seconds(),
+            half_open_max_requests: default_half_open_max_requests(),
+            success_threshold: default_success_threshold(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    changed_at: Instant,
+    trials_admitted: u32,
+    trial_successes: u32,
+}
+
+/// Closed/open/half-open state machine fed by both live traffic and health probes.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    inner: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                changed_at: Instant::now(),
+                trials_admitted: 0,
+                trial_successes: 0,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let mut inner = self.inner.lock().unwrap();
+        self.advance(&mut inner);
+        inner.state
+    }
+
+    /// Whether a request could be admitted right now, without reserving a trial slot.
+    pub fn allows_request(&self) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        self.advance(&mut inner);
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => inner.trials_admitted < self.config.half_open_max_requests,
+        }
+    }
+
+    /// Admits a request, reserving one of the trial slots when half-open.
+    pub fn try_acquire(&self) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        self.advance(&mut inner);
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => {
+                if inner.trials_admitted < self.config.half_open_max_requests {
+                    inner.trials_admitted += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Records a successful request. Returns the new state if it changed.
+    pub fn record_success(&self) -> Option<CircuitState> {
+        if !self.config.enabled {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => {
+                inner.consecutive_failures = 0;
+                None
+            }
+            CircuitState::Open => None,
+            CircuitState::HalfOpen => {
+                inner.trial_successes += 1;
+                if inner.trial_successes >= self.config.success_threshold {
+                    Some(Self::transition(&mut inner, CircuitState::Closed))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Records a failed request. Returns the new state if it changed.
+    pub fn record_failure(&self) -> Option<CircuitState> {
+        if !self.config.enabled {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => {
+                inner.consecutive_failures += 1;
+                if inner.consecutive_failures >= self.config.failure_threshold {
+                    Some(Self::transition(&mut inner, CircuitState::Open))
+                } else {
+                    None
+                }
+            }
+            CircuitState::Open => None,
+            CircuitState::HalfOpen => Some(Self::transition(&mut inner, CircuitState::Open)),
+        }
+    }
+
+    /// Records a health probe. Failed probes count towards `failure_threshold`
+    /// like failed requests; a passing probe only moves an open circuit to
+    /// half-open so traffic returns through trials.
+    pub fn record_probe(&self, success: bool) -> Option<CircuitState> {
+        if !success {
+            return self.record_failure();
+        }
+        if !self.config.enabled {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Open => Some(Self::transition(&mut inner, CircuitState::HalfOpen)),
+            _ => None,
+        }
+    }
+
+    fn advance(&self, inner: &mut BreakerState) {
+        let open_for = Duration::from_secs(self.config.open_seconds);
+        match inner.state {
+            CircuitState::Open if inner.changed_at.elapsed() >= open_for => {
+                Self::transition(inner, CircuitState::HalfOpen);
+            }
+            // Trials that never reported back (e.g. cancelled requests) must not
+            // wedge the circuit half-open, so hand out a fresh set of slots.
+            CircuitState::HalfOpen
+                if inner.trials_admitted >= self.config.half_open_max_requests
+                    && inner.changed_at.elapsed() >= open_for =>
+            {
+                inner.trials_admitted = 0;
+                inner.changed_at = Instant::now();
+            }
+            _ => {}
+        }
+    }
+
+    fn transition(inner: &mut BreakerState, state: CircuitState) -> CircuitState {
+        inner.state = state;
+        inner.changed_at = Instant::now();
+        inner.consecutive_failures = 0;
+        inner.trials_admitted = 0;
+        inner.trial_successes = 0;
+        state
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_open_seconds() -> u64 {
+    30
+}
+
+fn default_half_open_max_requests() -> u32 {
+    3
+}
+
+fn default_success_threshold() -> u32 {
+    3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: 3,
+            open_seconds: 3600,
+            half_open_max_requests: 2,
+            success_threshold: 2,
+        })
+    }
+
+    #[test]
+    fn failed_probes_count_towards_the_failure_threshold() {
+        let breaker = breaker();
+        assert_eq!(breaker.record_probe(false), None);
+        assert_eq!(breaker.record_failure(), None);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allows_request());
+
+        assert_eq!(breaker.record_probe(false), Some(CircuitState::Open));
+        assert!(!breaker.allows_request());
+    }
+
+    #[test]
+    fn a_successful_request_resets_the_failure_count() {
+        let breaker = breaker();
+        breaker.record_probe(false);
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_probe(false);
+        breaker.record_probe(false);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn passing_probes_only_move_an_open_circuit_to_half_open() {
+        let breaker = breaker();
+        assert_eq!(breaker.record_probe(true), None);
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        assert_eq!(breaker.record_probe(true), Some(CircuitState::HalfOpen));
+        assert_eq!(breaker.record_probe(true), None);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn half_open_trials_close_or_reopen_the_circuit() {
+        let breaker = breaker();
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        breaker.record_probe(true);
+        assert!(breaker.try_acquire());
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+        assert_eq!(breaker.record_success(), None);
+        assert_eq!(breaker.record_success(), Some(CircuitState::Closed));
+
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        breaker.record_probe(true);
+        assert_eq!(breaker.record_probe(false), Some(CircuitState::Open));
+    }
+
+    #[test]
+    fn a_disabled_breaker_never_opens() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            enabled: false,
+            ..CircuitBreakerConfig::default()
+        });
+        for _ in 0..10 {
+            breaker.record_failure();
+            breaker.record_probe(false);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire());
+    }
+}
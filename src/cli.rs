@@ -0,0 +1,254 @@
+use crate::config::ConfigLoader;
+use crate::ctl::CtlArgs;
+use crate::discovery;
+use crate::health::HealthCheck;
+use crate::model_manager::ModelManager;
+use crate::probes::Probe;
+use crate::validation::{Severity, ValidationReport};
+use crate::{Config, Endpoint};
+use clap::{Args, Parser, Subcommand};
+use futures_util::future::join_all;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::time;
+use tracing::Level;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Load balancer for a fleet of Ollama servers")]
+pub struct Cli {
+    /// YAML, TOML or JSON config file [default: config/config.yaml, if present]
+    #[arg(long, short, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Runs `serve` when no command is given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the load balancer
+    Serve(ServeArgs),
+    /// Parse the config and report any errors
+    Validate,
+    /// Probe every configured endpoint once and print its health and models
+    Check,
+    /// List the models installed across the fleet
+    Models,
+    /// Inspect and manage a running manager through its admin API
+    Ctl(CtlArgs),
+}
+
+/// Flags override `server` settings from the config file and environment.
+#[derive(Debug, Default, Args)]
+pub struct ServeArgs {
+    /// Address to listen on [default: 0.0.0.0:3000]
+    #[arg(long)]
+    pub listen: Option<SocketAddr>,
+
+    /// Address to serve the admin API on [default: 127.0.0.1:3001]
+    #[arg(long)]
+    pub admin_listen: Option<SocketAddr>,
+
+    /// One of trace, debug, info, warn or error [default: info]
+    #[arg(long)]
+    pub log_level: Option<Level>,
+}
+
+impl ServeArgs {
+    pub fn apply(&self, mut loader: ConfigLoader) -> ConfigLoader {
+        if let Some(listen) = self.listen {
+            loader = loader.with_override("server.listen", listen.to_string());
+        }
+        if let Some(admin_listen) = self.admin_listen {
+            loader = loader.with_override("server.admin_listen", admin_listen.to_string());
+        }
+        if let Some(level) = self.log_level {
+            loader = loader.with_override("server.log_level", level.to_string());
+        }
+        loader
+    }
+}
+
+/// Prints every problem in the config. Fails if any of them is an error.
+pub fn validate(report: &ValidationReport) -> anyhow::Result<()> {
+    for issue in &report.issues {
+        let severity = match issue.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{}: {}: {}", severity, issue.path, issue.message);
+        if let Some(hint) = &issue.hint {
+            println!("  hint: {}", hint);
+        }
+    }
+
+    let errors = report.errors().count();
+    let warnings = report.warnings().count();
+    if errors > 0 {
+        anyhow::bail!("config has {} errors and {} warnings", errors, warnings);
+    }
+    println!("Config is valid ({} warnings)", warnings);
+    Ok(())
+}
+
+struct CheckResult {
+    url: String,
+    health: Result<bool, String>,
+    latency: Duration,
+    models: Result<Vec<String>, String>,
+}
+
+async fn check_endpoint(
+    config: &Config,
+    endpoint: &Endpoint,
+    checker: &Probe,
+    model_manager: &ModelManager,
+) -> CheckResult {
+    let timeout = Duration::from_secs(
+        endpoint
+            .health_check_config()
+            .timeout_seconds
+            .unwrap_or(config.health_check.timeout_seconds),
+    );
+    let checker = endpoint.probe().unwrap_or(checker);
+    let started = Instant::now();
+    let health = match time::timeout(timeout, checker.check_health(endpoint)).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("no answer within {}s", timeout.as_secs())),
+    };
+    let latency = started.elapsed();
+    let models = match time::timeout(timeout, model_manager.list_models(endpoint)).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("no answer within {}s", timeout.as_secs())),
+    };
+    CheckResult {
+        url: endpoint.url.clone(),
+        health,
+        latency,
+        models,
+    }
+}
+
+async fn check_all(config: &Config) -> Vec<CheckResult> {
+    let checker = config.health_check.probe.build(&config.required_model);
+    let model_manager = ModelManager::new();
+    let mut endpoint_configs = config.static_endpoints();
+    match discovery::providers(config) {
+        Ok(providers) => {
+            for provider in providers {
+                match provider.discover().await {
+                    Ok(discovered) => endpoint_configs.extend(discovered.endpoints),
+                    Err(e) => eprintln!("Could not discover {}: {}", provider.source(), e),
+                }
+            }
+        }
+        Err(e) => eprintln!("Could not set up endpoint discovery: {}", e),
+    }
+    let events = broadcast::channel(1).0;
+    let endpoints: Vec<Endpoint> = endpoint_configs
+        .iter()
+        .map(|ec| crate::build_endpoint(config, ec, &events))
+        .collect();
+    join_all(
+        endpoints
+            .iter()
+            .map(|endpoint| check_endpoint(config, endpoint, &checker, &model_manager)),
+    )
+    .await
+}
+
+/// Probes every endpoint once and prints a table. Fails if none is healthy.
+pub async fn check(config: &Config) -> anyhow::Result<()> {
+    let results = check_all(config).await;
+
+    let rows: Vec<Vec<String>> = results
+        .iter()
+        .map(|result| {
+            let health = match &result.health {
+                Ok(true) => "healthy".to_string(),
+                Ok(false) => "unhealthy".to_string(),
+                Err(e) => format!("error: {}", e),
+            };
+            let models = match &result.models {
+                Ok(models) if models.is_empty() => "-".to_string(),
+                Ok(models) => models.join(", "),
+                Err(_) => "?".to_string(),
+            };
+            vec![
+                result.url.clone(),
+                health,
+                format!("{}ms", result.latency.as_millis()),
+                models,
+            ]
+        })
+        .collect();
+    print_table(&["ENDPOINT", "HEALTH", "LATENCY", "MODELS"], &rows);
+
+    let healthy = results
+        .iter()
+        .filter(|r| matches!(r.health, Ok(true)))
+        .count();
+    println!("\n{}/{} endpoints healthy", healthy, results.len());
+    if healthy == 0 {
+        anyhow::bail!("no healthy endpoints");
+    }
+    Ok(())
+}
+
+/// Prints which endpoints carry each model.
+pub async fn models(config: &Config) -> anyhow::Result<()> {
+    let results = check_all(config).await;
+
+    let mut inventory: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for result in &results {
+        match &result.models {
+            Ok(models) => {
+                for model in models {
+                    inventory.entry(model).or_default().push(&result.url);
+                }
+            }
+            Err(e) => eprintln!("Could not list models on {}: {}", result.url, e),
+        }
+    }
+
+    let rows: Vec<Vec<String>> = inventory
+        .iter()
+        .map(|(model, urls)| {
+            vec![
+                model.to_string(),
+                format!("{}/{}", urls.len(), results.len()),
+                urls.join(", "),
+            ]
+        })
+        .collect();
+    print_table(&["MODEL", "COVERAGE", "ENDPOINTS"], &rows);
+    Ok(())
+}
+
+pub(crate) fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", format_row(headers.to_vec()));
+    for row in rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
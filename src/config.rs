@@ -1,42 +1,557 @@
-use crate::error::Result;
+use crate::canary::CanaryConfig;
+use crate::circuit_breaker::CircuitBreakerConfig;
+use crate::discovery::DiscoveryConfig;
+use crate::dns::DnsDiscoveryConfig;
+use crate::error::{LoadBalancerError, Result};
+use crate::events::EventsConfig;
+use crate::failover::StreamFailoverConfig;
+use crate::outlier::OutlierDetectionConfig;
+use crate::priority::PriorityConfig;
+use crate::probes::ProbeConfig;
+use crate::registration::RegistrationConfig;
+use crate::routing::RoutingConfig;
+use crate::timeouts::TimeoutConfig;
+use crate::validation::ValidationReport;
 use serde::Deserialize;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config/config.yaml";
+
+/// Prefix of the environment variables that override config settings, e.g.
+/// `OLLAMA_MANAGER_HEALTH_CHECK__INTERVAL_SECONDS=10`.
+const ENV_PREFIX: &str = "OLLAMA_MANAGER";
+
+/// Comma-separated endpoint list that replaces the file's `endpoints`, e.g.
+/// `http://gpu1:11434;weight=2,http://gpu2:11434`.
+const ENV_ENDPOINTS: &str = "OLLAMA_MANAGER_ENDPOINTS";
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
+    #[serde(default)]
     pub endpoints: Vec<EndpointConfig>,
+    #[serde(default)]
     pub health_check: HealthCheckConfig,
+    #[serde(default = "default_strategy")]
     pub strategy: String,
+    #[serde(default)]
     pub retry: RetryConfig,
     pub required_model: String,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub priority: PriorityConfig,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub outlier_detection: OutlierDetectionConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub stream_failover: StreamFailoverConfig,
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub slow_start: SlowStartConfig,
+    #[serde(default)]
+    pub canary: CanaryConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub reload: ReloadConfig,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    #[serde(default)]
+    pub registration: RegistrationConfig,
     // pub max_body_size: usize,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct EndpointConfig {
+    /// Fixed endpoint URL. Leave out when `dns` discovers the endpoints instead.
+    #[serde(default)]
     pub url: String,
+    /// Resolves a DNS name into endpoints that share the rest of these settings.
+    #[serde(default)]
+    pub dns: Option<DnsDiscoveryConfig>,
+    #[serde(default = "default_weight")]
     pub weight: u32,
+    #[serde(default = "default_max_connections")]
     pub max_connections: u32,
+    /// Free-form tags describing the endpoint, e.g. `gpu: a100`.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Overrides of the global `health_check` schedule for this endpoint.
+    #[serde(default)]
+    pub health_check: EndpointHealthCheckConfig,
+}
+
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct EndpointHealthCheckConfig {
+    pub interval_seconds: Option<u64>,
+    pub timeout_seconds: Option<u64>,
+    pub probe: Option<ProbeConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ServerConfig {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    /// One of trace, debug, info, warn or error.
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// Address of the unauthenticated `/admin` API, kept apart from the proxy
+    /// so it is only reachable where this address is.
+    #[serde(default = "default_admin_listen")]
+    pub admin_listen: SocketAddr,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: default_listen(),
+            log_level: default_log_level(),
+            admin_listen: default_admin_listen(),
+        }
+    }
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 3000))
+}
+
+fn default_admin_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 3001))
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_strategy() -> String {
+    "round_robin".to_string()
+}
+
+pub(crate) fn default_weight() -> u32 {
+    1
+}
+
+pub(crate) fn default_max_connections() -> u32 {
+    100
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct HealthCheckConfig {
+    #[serde(default = "default_health_interval_seconds")]
     pub interval_seconds: u64,
+    #[serde(default = "default_health_timeout_seconds")]
     pub timeout_seconds: u64,
+    #[serde(default = "default_unhealthy_threshold")]
     pub unhealthy_threshold: u32,
+    #[serde(default = "default_healthy_threshold")]
     pub healthy_threshold: u32,
+    /// Random delay of up to this long added to each probe so checks spread out.
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// Shorter interval for endpoints that are not healthy, so failures are
+    /// confirmed and recoveries noticed sooner.
+    #[serde(default)]
+    pub unhealthy_interval_seconds: Option<u64>,
+    /// Probe used for endpoints that don't configure their own.
+    #[serde(default)]
+    pub probe: ProbeConfig,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: default_health_interval_seconds(),
+            timeout_seconds: default_health_timeout_seconds(),
+            unhealthy_threshold: default_unhealthy_threshold(),
+            healthy_threshold: default_healthy_threshold(),
+            jitter_ms: 0,
+            unhealthy_interval_seconds: None,
+            probe: ProbeConfig::default(),
+        }
+    }
+}
+
+fn default_health_interval_seconds() -> u64 {
+    5
+}
+
+fn default_health_timeout_seconds() -> u64 {
+    2
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct RetryConfig {
+    #[serde(default = "default_max_attempts")]
     pub max_attempts: u32,
+    #[serde(default = "default_initial_interval_ms")]
     pub initial_interval_ms: u64,
+    #[serde(default = "default_max_interval_ms")]
     pub max_interval_ms: u64,
+    /// Retries earned per request forwarded.
+    #[serde(default = "default_budget_ratio")]
+    pub budget_ratio: f64,
+    /// Retries available in reserve, also the cap on saved-up retries.
+    #[serde(default = "default_budget_reserve")]
+    pub budget_reserve: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShutdownConfig {
+    /// How long in-flight requests may keep running after SIGTERM/SIGINT.
+    #[serde(default = "default_grace_period_seconds")]
+    pub grace_period_seconds: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            grace_period_seconds: default_grace_period_seconds(),
+        }
+    }
+}
+
+fn default_grace_period_seconds() -> u64 {
+    30
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SlowStartConfig {
+    /// How long an endpoint that becomes healthy, after recovering or on first
+    /// joining, takes to reach its full weight. 0 disables slow start.
+    #[serde(default = "default_slow_start_window_seconds")]
+    pub window_seconds: u64,
+    /// Share of its weight such an endpoint starts from.
+    #[serde(default = "default_slow_start_initial_weight")]
+    pub initial_weight: f64,
+}
+
+impl Default for SlowStartConfig {
+    fn default() -> Self {
+        Self {
+            window_seconds: default_slow_start_window_seconds(),
+            initial_weight: default_slow_start_initial_weight(),
+        }
+    }
+}
+
+fn default_slow_start_window_seconds() -> u64 {
+    30
+}
+
+fn default_slow_start_initial_weight() -> f64 {
+    0.1
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReloadConfig {
+    /// Poll the config file for changes. SIGHUP reloads it either way.
+    #[serde(default = "default_reload_watch")]
+    pub watch: bool,
+    #[serde(default = "default_reload_poll_interval_seconds")]
+    pub poll_interval_seconds: u64,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: default_reload_watch(),
+            poll_interval_seconds: default_reload_poll_interval_seconds(),
+        }
+    }
+}
+
+fn default_reload_watch() -> bool {
+    true
+}
+
+fn default_reload_poll_interval_seconds() -> u64 {
+    2
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_interval_ms: default_initial_interval_ms(),
+            max_interval_ms: default_max_interval_ms(),
+            budget_ratio: default_budget_ratio(),
+            budget_reserve: default_budget_reserve(),
+        }
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_initial_interval_ms() -> u64 {
+    100
+}
+
+fn default_max_interval_ms() -> u64 {
+    1000
+}
+
+fn default_budget_ratio() -> f64 {
+    0.2
+}
+
+fn default_budget_reserve() -> u32 {
+    10
 }
 
 impl Config {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let contents = std::fs::read_to_string(path)?;
-        Ok(serde_yaml::from_str(&contents)?)
+    /// Loads the layered config from `path`, or from the default path if it exists.
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        ConfigLoader::new(path).load()
+    }
+
+    /// Endpoints listed by URL, as opposed to discovered through DNS.
+    pub fn static_endpoints(&self) -> Vec<EndpointConfig> {
+        self.endpoints
+            .iter()
+            .filter(|ec| ec.dns.is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Checks every setting, returning errors and warnings with their paths.
+    pub fn validate(&self) -> ValidationReport {
+        crate::validation::validate(self)
+    }
+}
+
+/// Builds a `Config` from layers, each overriding the one before: built-in
+/// defaults, the config file (YAML, TOML or JSON by extension), `OLLAMA_MANAGER_*`
+/// environment variables, then explicit overrides such as command-line flags.
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    path: PathBuf,
+    required: bool,
+    overrides: Vec<(String, String)>,
+}
+
+impl ConfigLoader {
+    /// A file given explicitly must exist; the default one may be absent when
+    /// everything comes from the environment.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            required: path.is_some(),
+            path: path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH)),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Sets a dotted key such as `server.listen`, taking precedence over every source.
+    pub fn with_override(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Parses file contents on their own, in the format the file's extension
+    /// names, without the environment or overrides on top.
+    pub fn parse_file(&self, contents: &str) -> Result<serde_json::Value> {
+        let extension = self
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let format = match extension.as_str() {
+            "toml" => ::config::FileFormat::Toml,
+            "json" => ::config::FileFormat::Json,
+            _ => ::config::FileFormat::Yaml,
+        };
+        ::config::Config::builder()
+            .add_source(::config::File::from_str(contents, format))
+            .build()
+            .and_then(|source| source.try_deserialize())
+            .map_err(config_error)
+    }
+
+    /// Loads the config, failing if it has errors. Warnings are dropped; use
+    /// `load_checked` to see them.
+    pub fn load(&self) -> Result<Config> {
+        let (config, report) = self.load_checked()?;
+        report.into_result()?;
+        Ok(config)
+    }
+
+    /// Loads the config along with every problem found in it: unknown fields,
+    /// invalid values and settings that have no effect. Fails outright only if
+    /// the sources can't be read or a value has the wrong type.
+    pub fn load_checked(&self) -> Result<(Config, ValidationReport)> {
+        let mut builder = ::config::Config::builder()
+            .add_source(::config::File::from(self.path.as_path()).required(self.required))
+            .add_source(
+                ::config::Environment::with_prefix(ENV_PREFIX)
+                    .prefix_separator("_")
+                    .separator("__")
+                    .try_parsing(true),
+            );
+
+        if let Ok(list) = std::env::var(ENV_ENDPOINTS) {
+            builder = builder
+                .set_override("endpoints", parse_endpoint_list(&list)?)
+                .map_err(config_error)?;
+        }
+        for (key, value) in &self.overrides {
+            builder = builder
+                .set_override(key.as_str(), value.as_str())
+                .map_err(config_error)?;
+        }
+
+        let source = builder.build().map_err(config_error)?;
+        let mut unknown = Vec::new();
+        let mut record_unknown =
+            |path: serde_ignored::Path| unknown.push(format_ignored_path(&path));
+        let deserializer = serde_ignored::Deserializer::new(source, &mut record_unknown);
+        let config: Config = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            let mut report = ValidationReport::default();
+            report.error(e.path().to_string(), e.inner().to_string());
+            LoadBalancerError::InvalidConfig(report)
+        })?;
+
+        let mut report = ValidationReport::default();
+        for path in unknown {
+            report
+                .error(path, "unknown field")
+                .hint("check the spelling against config/config.example.yaml");
+        }
+        report.issues.extend(config.validate().issues);
+        Ok((config, report))
+    }
+}
+
+/// Renders a path the way validation messages do, e.g. `endpoints[1].wieght`.
+fn format_ignored_path(path: &serde_ignored::Path) -> String {
+    match path {
+        serde_ignored::Path::Root => String::new(),
+        serde_ignored::Path::Seq { parent, index } => {
+            format!("{}[{}]", format_ignored_path(parent), index)
+        }
+        serde_ignored::Path::Map { parent, key } => match format_ignored_path(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => format_ignored_path(parent),
+    }
+}
+
+fn config_error(e: ::config::ConfigError) -> LoadBalancerError {
+    LoadBalancerError::ConfigError(e.to_string())
+}
+
+/// Parses `url[;key=value...]` entries separated by commas into endpoint tables.
+/// Dotted keys such as `labels.gpu` or `health_check.interval_seconds` set
+/// nested settings.
+fn parse_endpoint_list(list: &str) -> Result<Vec<::config::Value>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut parts = entry.split(';');
+            let mut endpoint = ::config::Map::new();
+            let url = parts.next().unwrap_or_default().trim();
+            endpoint.insert("url".to_string(), ::config::Value::from(url));
+            for part in parts {
+                let (key, value) = part.split_once('=').ok_or_else(|| {
+                    LoadBalancerError::ConfigError(format!(
+                        "{}: expected key=value after {}, got {:?}",
+                        ENV_ENDPOINTS, url, part
+                    ))
+                })?;
+                let value = match value.trim().parse::<i64>() {
+                    Ok(number) => ::config::Value::from(number),
+                    Err(_) => ::config::Value::from(value.trim()),
+                };
+                insert_nested(&mut endpoint, key.trim(), value).map_err(|key| {
+                    LoadBalancerError::ConfigError(format!(
+                        "{}: {} of {} is set both as a value and as a table",
+                        ENV_ENDPOINTS, key, url
+                    ))
+                })?;
+            }
+            Ok(::config::Value::from(endpoint))
+        })
+        .collect()
+}
+
+/// Sets a dotted key in a table, creating the tables on the way. Fails with
+/// the conflicting key if one of them already holds a plain value.
+fn insert_nested(
+    table: &mut ::config::Map<String, ::config::Value>,
+    key: &str,
+    value: ::config::Value,
+) -> std::result::Result<(), String> {
+    let Some((head, rest)) = key.split_once('.') else {
+        table.insert(key.to_string(), value);
+        return Ok(());
+    };
+    let entry = table
+        .entry(head.to_string())
+        .or_insert_with(|| ::config::Value::from(::config::Map::<String, ::config::Value>::new()));
+    match &mut entry.kind {
+        ::config::ValueKind::Table(inner) => {
+            insert_nested(inner, rest, value).map_err(|key| format!("{}.{}", head, key))
+        }
+        _ => Err(head.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_lists_set_nested_keys() {
+        let endpoints: Vec<EndpointConfig> = parse_endpoint_list(
+            "http://gpu1:11434;weight=2, http://gpu2:11434;labels.gpu=a100;labels.zone=rack2;\
+             health_check.interval_seconds=10;health_check.probe.type=tcp",
+        )
+        .unwrap()
+        .into_iter()
+        .map(|value| value.try_deserialize().unwrap())
+        .collect();
+
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[0].url, "http://gpu1:11434");
+        assert_eq!(endpoints[0].weight, 2);
+        assert!(endpoints[0].labels.is_empty());
+        assert_eq!(endpoints[1].labels["gpu"], "a100");
+        assert_eq!(endpoints[1].labels["zone"], "rack2");
+        assert_eq!(endpoints[1].health_check.interval_seconds, Some(10));
+        assert!(matches!(
+            endpoints[1].health_check.probe,
+            Some(ProbeConfig::Tcp)
+        ));
+    }
+
+    #[test]
+    fn endpoint_lists_reject_conflicting_keys() {
+        assert!(parse_endpoint_list("http://gpu1:11434;labels=a100;labels.gpu=a100").is_err());
+        assert!(parse_endpoint_list("http://gpu1:11434;weight").is_err());
     }
 }
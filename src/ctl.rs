@@ -0,0 +1,601 @@
+use crate::admin::{DrainStatus, EndpointModels, EndpointStatus, PullStatus};
+use crate::cli::print_table;
+use crate::events::Event;
+use crate::{CircuitState, Config};
+use anyhow::{bail, Context};
+use clap::{Args, Subcommand};
+use futures_util::StreamExt;
+use reqwest::{Method, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::time;
+
+const PULL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+#[derive(Debug, Args)]
+pub struct CtlArgs {
+    /// Admin API of the running manager [default: server.admin_listen from the config]
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Print JSON instead of tables
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: CtlCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CtlCommand {
+    /// List endpoints with their health and load
+    Endpoints,
+    /// List the models installed across the fleet
+    Models,
+    /// Stop sending new requests to an endpoint
+    Drain { url: String },
+    /// Put a drained endpoint back into rotation
+    Undrain { url: String },
+    /// Add an endpoint until it is removed or the manager restarts
+    Add {
+        url: String,
+        #[arg(long, default_value_t = 1)]
+        weight: u32,
+        #[arg(long, default_value_t = 100)]
+        max_connections: u32,
+        /// Label as key=value; may be repeated
+        #[arg(long = "label", value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+    },
+    /// Remove an endpoint, letting its in-flight requests finish
+    Remove { url: String },
+    /// Pull a model onto an endpoint and follow its progress
+    Pull {
+        url: String,
+        /// Model to pull [default: the manager's required_model]
+        model: Option<String>,
+        /// Return once the pull has started
+        #[arg(long)]
+        detach: bool,
+    },
+    /// Show model pulls started through the admin API
+    Pulls,
+    /// Follow the fleet event stream
+    Events {
+        /// Only show events of this endpoint
+        #[arg(long)]
+        endpoint: Option<String>,
+    },
+}
+
+fn parse_label(label: &str) -> Result<(String, String), String> {
+    match label.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value, got {:?}", label)),
+    }
+}
+
+/// Where the admin API of a manager started with `config` listens.
+fn default_url(config: Option<&Config>) -> String {
+    let server = config.map(|c| c.server.clone()).unwrap_or_default();
+    let addr = server.admin_listen;
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    format!("http://{}", SocketAddr::new(ip, addr.port()))
+}
+
+struct AdminClient {
+    base: String,
+    client: reqwest::Client,
+}
+
+impl AdminClient {
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> anyhow::Result<Response> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.base, path));
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("could not reach the admin API at {}", self.base))?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|body| body.get("error")?.as_str().map(str::to_string))
+            .unwrap_or(text);
+        bail!("{}: {}", status, message)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        Ok(self.request(Method::GET, path, None).await?.json().await?)
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        Ok(self
+            .request(method, path, Some(&body))
+            .await?
+            .json()
+            .await?)
+    }
+}
+
+/// Runs a `ctl` command against the admin API of a running manager.
+pub async fn run(args: CtlArgs, config: Option<Config>) -> anyhow::Result<()> {
+    let base = args
+        .url
+        .clone()
+        .unwrap_or_else(|| default_url(config.as_ref()));
+    let admin = AdminClient {
+        base: base.trim_end_matches('/').to_string(),
+        client: reqwest::Client::new(),
+    };
+    let json = args.json;
+
+    match args.command {
+        CtlCommand::Endpoints => {
+            let endpoints: Vec<EndpointStatus> = admin.get("/admin/endpoints").await?;
+            if json {
+                return print_json(&endpoints);
+            }
+            print_endpoints(&endpoints);
+        }
+        CtlCommand::Models => {
+            let models: Vec<EndpointModels> = admin.get("/admin/models").await?;
+            if json {
+                return print_json(&models);
+            }
+            print_models(&models);
+        }
+        CtlCommand::Drain { url } => {
+            set_draining(&admin, "/admin/endpoints/drain", &url, json).await?
+        }
+        CtlCommand::Undrain { url } => {
+            set_draining(&admin, "/admin/endpoints/undrain", &url, json).await?
+        }
+        CtlCommand::Add {
+            url,
+            weight,
+            max_connections,
+            labels,
+        } => {
+            let labels: BTreeMap<String, String> = labels.into_iter().collect();
+            let body = serde_json::json!({
+                "url": url,
+                "weight": weight,
+                "max_connections": max_connections,
+                "labels": labels,
+            });
+            let endpoint: EndpointStatus =
+                admin.send(Method::POST, "/admin/endpoints", body).await?;
+            if json {
+                return print_json(&endpoint);
+            }
+            println!(
+                "Added {} (weight {}, max_connections {})",
+                endpoint.url, endpoint.weight, endpoint.max_connections
+            );
+        }
+        CtlCommand::Remove { url } => {
+            let query = reqwest::Url::parse_with_params("http://admin/", &[("url", &url)])?;
+            let path = format!("/admin/endpoints?{}", query.query().unwrap_or_default());
+            admin.request(Method::DELETE, &path, None).await?;
+            if !json {
+                println!("Removed {}", url);
+            }
+        }
+        CtlCommand::Pull { url, model, detach } => {
+            let status: PullStatus = admin
+                .send(
+                    Method::POST,
+                    "/admin/endpoints/pull",
+                    serde_json::json!({ "url": url, "model": model }),
+                )
+                .await?;
+            if detach {
+                return if json {
+                    print_json(&status)
+                } else {
+                    println!("Pulling {} on {}", status.model, status.url);
+                    Ok(())
+                };
+            }
+            follow_pull(&admin, status, json).await?;
+        }
+        CtlCommand::Pulls => {
+            let pulls: Vec<PullStatus> = admin.get("/admin/endpoints/pulls").await?;
+            if json {
+                return print_json(&pulls);
+            }
+            let rows: Vec<Vec<String>> = pulls
+                .iter()
+                .map(|pull| {
+                    vec![
+                        pull.url.clone(),
+                        pull.model.clone(),
+                        pull_state(pull),
+                        progress_bar(pull),
+                    ]
+                })
+                .collect();
+            print_table(&["ENDPOINT", "MODEL", "STATE", "PROGRESS"], &rows);
+        }
+        CtlCommand::Events { endpoint } => tail_events(&admin, endpoint.as_deref(), json).await?,
+    }
+    Ok(())
+}
+
+fn print_json(value: &impl Serialize) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn set_draining(
+    admin: &AdminClient,
+    path: &str,
+    url: &str,
+    json: bool,
+) -> anyhow::Result<()> {
+    let status: DrainStatus = admin
+        .send(Method::POST, path, serde_json::json!({ "url": url }))
+        .await?;
+    if json {
+        return print_json(&status);
+    }
+    match (status.draining, status.drained) {
+        (true, true) => println!("{} is drained", status.url),
+        (true, false) => println!(
+            "{} is draining, {} requests in flight",
+            status.url, status.current_connections
+        ),
+        (false, _) => println!("{} is back in rotation", status.url),
+    }
+    Ok(())
+}
+
+fn print_endpoints(endpoints: &[EndpointStatus]) {
+    let rows: Vec<Vec<String>> = endpoints
+        .iter()
+        .map(|endpoint| {
+            let mut notes = Vec::new();
+            if endpoint.draining {
+                notes.push("draining".to_string());
+            }
+            if endpoint.ejected {
+                notes.push("ejected".to_string());
+            }
+            if endpoint.circuit != CircuitState::Closed {
+                notes.push(format!("circuit {}", endpoint.circuit.as_str()));
+            }
+            for model in endpoint.canary_failures.keys() {
+                notes.push(format!("canary failing for {}", model));
+            }
+            let labels: Vec<String> = endpoint
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            vec![
+                endpoint.url.clone(),
+                endpoint.state.as_str().to_string(),
+                format!("{} ({:.2})", endpoint.weight, endpoint.effective_weight),
+                format!(
+                    "{}/{}",
+                    endpoint.current_connections, endpoint.max_connections
+                ),
+                if labels.is_empty() {
+                    "-".to_string()
+                } else {
+                    labels.join(",")
+                },
+                notes.join(", "),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "ENDPOINT",
+            "STATE",
+            "WEIGHT",
+            "CONNECTIONS",
+            "LABELS",
+            "NOTES",
+        ],
+        &rows,
+    );
+}
+
+fn print_models(endpoints: &[EndpointModels]) {
+    let mut inventory: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for endpoint in endpoints {
+        match &endpoint.error {
+            Some(e) => eprintln!("Could not list models on {}: {}", endpoint.url, e),
+            None => {
+                for model in &endpoint.models {
+                    inventory.entry(model).or_default().push(&endpoint.url);
+                }
+            }
+        }
+    }
+
+    let rows: Vec<Vec<String>> = inventory
+        .iter()
+        .map(|(model, urls)| {
+            vec![
+                model.to_string(),
+                format!("{}/{}", urls.len(), endpoints.len()),
+                urls.join(", "),
+            ]
+        })
+        .collect();
+    print_table(&["MODEL", "COVERAGE", "ENDPOINTS"], &rows);
+}
+
+fn pull_state(pull: &PullStatus) -> String {
+    match (&pull.error, pull.done) {
+        (Some(e), _) => format!("failed: {}", e),
+        (None, true) => "done".to_string(),
+        (None, false) => pull.progress.status.clone(),
+    }
+}
+
+fn progress_bar(pull: &PullStatus) -> String {
+    let fraction = match (pull.progress.completed, pull.progress.total) {
+        _ if pull.done && pull.error.is_none() => 1.0,
+        (Some(completed), Some(total)) if total > 0 => completed.min(total) as f64 / total as f64,
+        _ => return "-".to_string(),
+    };
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    let mut bar = format!(
+        "[{}{}] {:>3}%",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled),
+        (fraction * 100.0) as u32
+    );
+    if let (Some(completed), Some(total)) = (pull.progress.completed, pull.progress.total) {
+        bar.push_str(&format!(
+            " {}/{}",
+            human_bytes(completed),
+            human_bytes(total)
+        ));
+    }
+    bar
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit + 1 < UNITS.len() {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Polls a pull until it finishes, drawing a progress bar on a terminal and
+/// printing a line per change otherwise.
+async fn follow_pull(admin: &AdminClient, started: PullStatus, json: bool) -> anyhow::Result<()> {
+    let interactive = !json && std::io::stderr().is_terminal();
+    let mut last = String::new();
+    let pull = loop {
+        let pulls: Vec<PullStatus> = admin.get("/admin/endpoints/pulls").await?;
+        let pull = pulls
+            .into_iter()
+            .find(|pull| pull.url == started.url && pull.model == started.model)
+            .context("the pull is no longer listed by the manager")?;
+
+        let line = if json {
+            serde_json::to_string(&pull)?
+        } else {
+            format!("{} {}", progress_bar(&pull), pull_state(&pull))
+        };
+        if line != last {
+            if interactive {
+                eprint!("\r\x1b[2K{}", line);
+                std::io::stderr().flush()?;
+            } else if json {
+                println!("{}", line);
+            } else {
+                eprintln!("{}", line);
+            }
+            last = line;
+        }
+        if pull.done {
+            break pull;
+        }
+        time::sleep(PULL_POLL_INTERVAL).await;
+    };
+    if interactive {
+        eprintln!();
+    }
+
+    if let Some(e) = pull.error {
+        bail!("pulling {} on {} failed: {}", pull.model, pull.url, e);
+    }
+    if !json {
+        println!("Pulled {} on {}", pull.model, pull.url);
+    }
+    Ok(())
+}
+
+/// Prints fleet events as they arrive until the manager closes the stream.
+async fn tail_events(
+    admin: &AdminClient,
+    endpoint: Option<&str>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let response = admin.request(Method::GET, "/admin/events", None).await?;
+    let mut stream = response.bytes_stream();
+    let mut buffer = String::new();
+    let (mut name, mut data) = (String::new(), String::new());
+
+    while let Some(chunk) = stream.next().await {
+        buffer.push_str(&String::from_utf8_lossy(&chunk?));
+        while let Some(newline) = buffer.find('\n') {
+            let line: String = buffer.drain(..=newline).collect();
+            let line = line.trim_end_matches(['\r', '\n']);
+            if let Some(value) = line.strip_prefix("event:") {
+                name = value.trim().to_string();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data.push_str(value.trim_start());
+            } else if line.is_empty() && !data.is_empty() {
+                print_event(&name, &data, endpoint, json)?;
+                name.clear();
+                data.clear();
+            }
+        }
+    }
+    bail!("the manager closed the event stream")
+}
+
+fn print_event(name: &str, data: &str, endpoint: Option<&str>, json: bool) -> anyhow::Result<()> {
+    if name == "lagged" {
+        eprintln!("Missed {} events, the client fell behind", data);
+        return Ok(());
+    }
+    let event: Event = serde_json::from_str(data)?;
+    if let Some(endpoint) = endpoint {
+        if event.endpoint.trim_end_matches('/') != endpoint.trim_end_matches('/') {
+            return Ok(());
+        }
+    }
+    if json {
+        println!("{}", data);
+        return Ok(());
+    }
+
+    let details = match serde_json::to_value(&event.kind)? {
+        serde_json::Value::Object(fields) => fields
+            .into_iter()
+            .filter(|(key, _)| key != "type")
+            .map(|(key, value)| match value {
+                serde_json::Value::String(value) => format!("{}={}", key, value),
+                value => format!("{}={}", key, value),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => String::new(),
+    };
+    let seconds = event.at / 1000 % 86400;
+    let line = format!(
+        "{:02}:{:02}:{:02}.{:03}  {}  {}  {}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        event.at % 1000,
+        event.endpoint,
+        event.kind.name(),
+        details
+    );
+    println!("{}", line.trim_end());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_manager::PullProgress;
+
+    fn pull(completed: Option<u64>, total: Option<u64>, done: bool) -> PullStatus {
+        PullStatus {
+            url: "http://gpu1:11434".to_string(),
+            model: "llama3".to_string(),
+            progress: PullProgress {
+                status: "downloading".to_string(),
+                total,
+                completed,
+                ..Default::default()
+            },
+            done,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn labels_are_key_value_pairs() {
+        assert_eq!(
+            parse_label("gpu=a100").unwrap(),
+            ("gpu".to_string(), "a100".to_string())
+        );
+        assert_eq!(
+            parse_label("zone=").unwrap(),
+            ("zone".to_string(), String::new())
+        );
+        assert!(parse_label("gpu").is_err());
+        assert!(parse_label("=a100").is_err());
+    }
+
+    #[test]
+    fn default_url_reaches_the_admin_listener_locally() {
+        assert_eq!(default_url(None), "http://127.0.0.1:3001");
+        let config = |admin_listen: &str| -> Config {
+            serde_yaml::from_str(&format!(
+                "required_model: llama3\nserver:\n  admin_listen: \"{}\"\n",
+                admin_listen
+            ))
+            .unwrap()
+        };
+        assert_eq!(
+            default_url(Some(&config("0.0.0.0:4001"))),
+            "http://127.0.0.1:4001"
+        );
+        assert_eq!(default_url(Some(&config("[::]:4001"))), "http://[::1]:4001");
+        assert_eq!(
+            default_url(Some(&config("10.0.0.5:4001"))),
+            "http://10.0.0.5:4001"
+        );
+    }
+
+    #[test]
+    fn byte_counts_are_human_readable() {
+        assert_eq!(human_bytes(999), "999 B");
+        assert_eq!(human_bytes(1_500), "1.5 KB");
+        assert_eq!(human_bytes(4_700_000_000), "4.7 GB");
+    }
+
+    #[test]
+    fn progress_bars_follow_the_pull() {
+        assert_eq!(progress_bar(&pull(None, None, false)), "-");
+        let half = progress_bar(&pull(Some(500), Some(1000), false));
+        assert!(half.starts_with(&format!("[{}{}]", "#".repeat(15), "-".repeat(15))));
+        assert!(half.ends_with(" 50% 500 B/1.0 KB"), "{}", half);
+        assert!(progress_bar(&pull(None, None, true)).ends_with("100%"));
+
+        let mut failed = pull(Some(10), Some(1000), true);
+        failed.error = Some("disk full".to_string());
+        assert!(progress_bar(&failed).contains("  1%"));
+        assert_eq!(pull_state(&failed), "failed: disk full");
+        assert_eq!(pull_state(&pull(None, None, true)), "done");
+        assert_eq!(pull_state(&pull(None, None, false)), "downloading");
+    }
+}
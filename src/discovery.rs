@@ -0,0 +1,223 @@
+use crate::config::{Config, EndpointConfig};
+use crate::dns::{self, Resolver, SystemResolver};
+use crate::error::{LoadBalancerError, Result};
+use crate::validation::{endpoint_url_problem, label_problem};
+use crate::LoadBalancer;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{info, warn};
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DiscoveryConfig {
+    #[serde(default)]
+    pub files: Vec<FileDiscoveryConfig>,
+}
+
+/// A file of endpoint definitions kept up to date by other tooling.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FileDiscoveryConfig {
+    /// A JSON or YAML file, or a directory whose `.json`, `.yaml` and `.yml`
+    /// files are all read.
+    pub path: PathBuf,
+    #[serde(default = "default_poll_interval_seconds")]
+    pub poll_interval_seconds: u64,
+}
+
+fn default_poll_interval_seconds() -> u64 {
+    5
+}
+
+/// The endpoints a provider currently offers, and when to look again.
+#[derive(Debug)]
+pub struct Discovered {
+    pub endpoints: Vec<EndpointConfig>,
+    pub next_refresh: Duration,
+}
+
+/// A source of endpoints that change at runtime. Each provider maintains its
+/// own endpoint set in the load balancer, next to the config file's.
+#[async_trait]
+pub trait Discovery: Send + Sync {
+    /// Name of the endpoint set this provider maintains, e.g. `dns:gpu.internal`.
+    fn source(&self) -> String;
+
+    async fn discover(&self) -> Result<Discovered>;
+
+    /// How long to wait after a failed lookup before trying again.
+    fn retry_interval(&self) -> Duration;
+
+    /// Looks up the endpoints and syncs the load balancer, returning when to
+    /// do so next. A failed lookup keeps the last known endpoints.
+    async fn refresh(&self, load_balancer: &LoadBalancer) -> Duration {
+        match self.discover().await {
+            Ok(discovered) => {
+                let changes = load_balancer.set_endpoints(&self.source(), discovered.endpoints);
+                if !changes.is_empty() {
+                    info!(
+                        "{} endpoints changed: added {:?}, removed {:?}, updated {:?}",
+                        self.source(),
+                        changes.added,
+                        changes.removed,
+                        changes.updated
+                    );
+                }
+                discovered.next_refresh
+            }
+            Err(e) => {
+                warn!(
+                    "Discovery through {} failed, keeping its last known endpoints: {}",
+                    self.source(),
+                    e
+                );
+                self.retry_interval()
+            }
+        }
+    }
+}
+
+/// A provider shared with other parts of the server, e.g. the self-registration
+/// routes.
+#[async_trait]
+impl<T: Discovery + ?Sized> Discovery for Arc<T> {
+    fn source(&self) -> String {
+        (**self).source()
+    }
+
+    async fn discover(&self) -> Result<Discovered> {
+        (**self).discover().await
+    }
+
+    fn retry_interval(&self) -> Duration {
+        (**self).retry_interval()
+    }
+}
+
+/// Refreshes once, so the first endpoints are in place before serving, then
+/// keeps refreshing in the background.
+pub async fn start(
+    discovery: Box<dyn Discovery>,
+    load_balancer: Arc<LoadBalancer>,
+) -> JoinHandle<()> {
+    let mut next = discovery.refresh(&load_balancer).await;
+    info!(
+        "Discovering endpoints through {}, next refresh in {}s",
+        discovery.source(),
+        next.as_secs()
+    );
+    tokio::spawn(async move {
+        loop {
+            time::sleep(next).await;
+            next = discovery.refresh(&load_balancer).await;
+        }
+    })
+}
+
+/// Every provider the configuration asks for: DNS names listed under
+/// `endpoints` and the files under `discovery.files`.
+pub fn providers(config: &Config) -> Result<Vec<Box<dyn Discovery>>> {
+    let mut providers: Vec<Box<dyn Discovery>> = Vec::new();
+    if config.endpoints.iter().any(|ec| ec.dns.is_some()) {
+        let resolver: Arc<dyn Resolver> = Arc::new(SystemResolver::new()?);
+        for discovery in dns::discoveries(config, resolver) {
+            providers.push(Box::new(discovery));
+        }
+    }
+    for file in &config.discovery.files {
+        providers.push(Box::new(FileDiscovery::new(file.clone())));
+    }
+    Ok(providers)
+}
+
+/// Reads endpoint definitions from a file or directory. Each file holds a list
+/// of endpoints, or a mapping with an `endpoints` list, in the format of the
+/// config file's `endpoints` entries (`url`, `weight`, `max_connections`,
+/// `labels`, `health_check`).
+pub struct FileDiscovery {
+    config: FileDiscoveryConfig,
+}
+
+impl FileDiscovery {
+    pub fn new(config: FileDiscoveryConfig) -> Self {
+        Self { config }
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let path = &self.config.path;
+        if !path.is_dir() {
+            return Ok(vec![path.clone()]);
+        }
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(|e| read_error(path, e))? {
+            let file = entry.map_err(|e| read_error(path, e))?.path();
+            let extension = file
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            if file.is_file() && matches!(extension, "json" | "yaml" | "yml") {
+                files.push(file);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+fn read_error(path: &Path, error: impl std::fmt::Display) -> LoadBalancerError {
+    LoadBalancerError::DiscoveryError(format!("{}: {}", path.display(), error))
+}
+
+/// Parses one file of endpoint definitions. JSON is read as YAML.
+fn parse_endpoints(path: &Path, contents: &str) -> Result<Vec<EndpointConfig>> {
+    let mut value: serde_yaml::Value =
+        serde_yaml::from_str(contents).map_err(|e| read_error(path, e))?;
+    if let Some(endpoints) = value.get_mut("endpoints") {
+        value = std::mem::take(endpoints);
+    }
+    if value.is_null() {
+        return Ok(Vec::new());
+    }
+    let endpoints: Vec<EndpointConfig> =
+        serde_yaml::from_value(value).map_err(|e| read_error(path, e))?;
+
+    for (i, endpoint) in endpoints.iter().enumerate() {
+        let problem = if endpoint.dns.is_some() {
+            Some("dns entries are only supported in the config file".to_string())
+        } else if endpoint.weight == 0 {
+            Some("weight must be at least 1".to_string())
+        } else {
+            endpoint_url_problem(&endpoint.url).or_else(|| label_problem(&endpoint.labels))
+        };
+        if let Some(problem) = problem {
+            return Err(read_error(path, format!("endpoints[{}]: {}", i, problem)));
+        }
+    }
+    Ok(endpoints)
+}
+
+#[async_trait]
+impl Discovery for FileDiscovery {
+    fn source(&self) -> String {
+        format!("file:{}", self.config.path.display())
+    }
+
+    async fn discover(&self) -> Result<Discovered> {
+        let mut endpoints: Vec<EndpointConfig> = Vec::new();
+        for file in self.files()? {
+            let contents = std::fs::read_to_string(&file).map_err(|e| read_error(&file, e))?;
+            endpoints.extend(parse_endpoints(&file, &contents)?);
+        }
+        Ok(Discovered {
+            endpoints,
+            next_refresh: self.retry_interval(),
+        })
+    }
+
+    fn retry_interval(&self) -> Duration {
+        Duration::from_secs(self.config.poll_interval_seconds.max(1))
+    }
+}
@@ -0,0 +1,236 @@
+use crate::config::{Config, EndpointConfig};
+use crate::discovery::{Discovered, Discovery};
+use crate::error::{LoadBalancerError, Result};
+use async_trait::async_trait;
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::TokioAsyncResolver;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Where to find the endpoints of a DNS-discovered entry. Exactly one of
+/// `host` and `srv` is set.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct DnsDiscoveryConfig {
+    /// Name whose A/AAAA records each become an endpoint on `port`.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// SRV record whose targets each become an endpoint, e.g. `_ollama._tcp.gpu.internal`.
+    #[serde(default)]
+    pub srv: Option<String>,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+    /// Bounds on the re-resolve interval, which otherwise follows the record TTL.
+    #[serde(default = "default_min_refresh_seconds")]
+    pub min_refresh_seconds: u64,
+    #[serde(default = "default_max_refresh_seconds")]
+    pub max_refresh_seconds: u64,
+}
+
+impl DnsDiscoveryConfig {
+    pub fn name(&self) -> &str {
+        self.host
+            .as_deref()
+            .or(self.srv.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+fn default_port() -> u16 {
+    11434
+}
+
+fn default_scheme() -> String {
+    "http".to_string()
+}
+
+fn default_min_refresh_seconds() -> u64 {
+    5
+}
+
+fn default_max_refresh_seconds() -> u64 {
+    300
+}
+
+/// Records returned for a query and how long they may be cached.
+#[derive(Debug, Clone)]
+pub struct DnsAnswer<T> {
+    pub records: Vec<T>,
+    pub ttl: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrvTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Looks up DNS records. A name without records answers with an empty list
+/// rather than an error, so its endpoints are removed.
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    async fn lookup_ip(&self, host: &str) -> Result<DnsAnswer<IpAddr>>;
+    async fn lookup_srv(&self, name: &str) -> Result<DnsAnswer<SrvTarget>>;
+}
+
+/// Resolver using the system's DNS configuration.
+pub struct SystemResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl SystemResolver {
+    pub fn new() -> Result<Self> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(|e| LoadBalancerError::DnsError(e.to_string()))?;
+        Ok(Self { resolver })
+    }
+}
+
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn lookup_ip(&self, host: &str) -> Result<DnsAnswer<IpAddr>> {
+        match self.resolver.lookup_ip(host).await {
+            Ok(lookup) => Ok(DnsAnswer {
+                records: lookup.iter().collect(),
+                ttl: lookup
+                    .valid_until()
+                    .saturating_duration_since(Instant::now()),
+            }),
+            Err(e) => no_records(e),
+        }
+    }
+
+    async fn lookup_srv(&self, name: &str) -> Result<DnsAnswer<SrvTarget>> {
+        match self.resolver.srv_lookup(name).await {
+            Ok(lookup) => Ok(DnsAnswer {
+                records: lookup
+                    .iter()
+                    .map(|srv| SrvTarget {
+                        host: srv.target().to_utf8().trim_end_matches('.').to_string(),
+                        port: srv.port(),
+                    })
+                    .collect(),
+                ttl: lookup
+                    .as_lookup()
+                    .valid_until()
+                    .saturating_duration_since(Instant::now()),
+            }),
+            Err(e) => no_records(e),
+        }
+    }
+}
+
+/// Turns a negative answer into an empty one, cached for its negative TTL.
+fn no_records<T>(error: ResolveError) -> Result<DnsAnswer<T>> {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound { negative_ttl, .. } => Ok(DnsAnswer {
+            records: Vec::new(),
+            ttl: Duration::from_secs(negative_ttl.unwrap_or(0) as u64),
+        }),
+        _ => Err(LoadBalancerError::DnsError(error.to_string())),
+    }
+}
+
+/// Keeps the endpoints behind one DNS name in sync with its records.
+pub struct DnsDiscovery {
+    config: DnsDiscoveryConfig,
+    /// Settings shared by every endpoint the name resolves to.
+    template: EndpointConfig,
+    resolver: Arc<dyn Resolver>,
+}
+
+impl DnsDiscovery {
+    pub fn new(
+        config: DnsDiscoveryConfig,
+        template: EndpointConfig,
+        resolver: Arc<dyn Resolver>,
+    ) -> Self {
+        Self {
+            config,
+            template,
+            resolver,
+        }
+    }
+
+    /// The endpoints the name currently resolves to, and how long that holds.
+    pub async fn resolve(&self) -> Result<(Vec<EndpointConfig>, Duration)> {
+        let scheme = &self.config.scheme;
+        let (urls, ttl) = match (&self.config.host, &self.config.srv) {
+            (Some(host), _) => {
+                let answer = self.resolver.lookup_ip(host).await?;
+                let urls = answer
+                    .records
+                    .iter()
+                    .map(|ip| match ip {
+                        IpAddr::V4(ip) => format!("{}://{}:{}", scheme, ip, self.config.port),
+                        IpAddr::V6(ip) => format!("{}://[{}]:{}", scheme, ip, self.config.port),
+                    })
+                    .collect::<Vec<_>>();
+                (urls, answer.ttl)
+            }
+            (None, Some(srv)) => {
+                let answer = self.resolver.lookup_srv(srv).await?;
+                let urls = answer
+                    .records
+                    .iter()
+                    .map(|target| format!("{}://{}:{}", scheme, target.host, target.port))
+                    .collect::<Vec<_>>();
+                (urls, answer.ttl)
+            }
+            (None, None) => {
+                return Err(LoadBalancerError::ConfigError(
+                    "dns discovery needs a host or srv name".to_string(),
+                ))
+            }
+        };
+
+        let mut endpoints: Vec<EndpointConfig> = Vec::new();
+        for url in urls {
+            if !endpoints.iter().any(|ec| ec.url == url) {
+                endpoints.push(EndpointConfig {
+                    url,
+                    dns: None,
+                    ..self.template.clone()
+                });
+            }
+        }
+        Ok((endpoints, ttl))
+    }
+}
+
+#[async_trait]
+impl Discovery for DnsDiscovery {
+    fn source(&self) -> String {
+        format!("dns:{}", self.config.name())
+    }
+
+    /// The resolved endpoints, refreshed as the record TTL expires within the
+    /// configured bounds.
+    async fn discover(&self) -> Result<Discovered> {
+        let (endpoints, ttl) = self.resolve().await?;
+        let max = Duration::from_secs(self.config.max_refresh_seconds).max(self.retry_interval());
+        Ok(Discovered {
+            endpoints,
+            next_refresh: ttl.clamp(self.retry_interval(), max),
+        })
+    }
+
+    fn retry_interval(&self) -> Duration {
+        Duration::from_secs(self.config.min_refresh_seconds)
+    }
+}
+
+/// One discovery per `endpoints` entry that names a DNS record.
+pub fn discoveries(config: &Config, resolver: Arc<dyn Resolver>) -> Vec<DnsDiscovery> {
+    config
+        .endpoints
+        .iter()
+        .filter_map(|ec| {
+            let dns = ec.dns.clone()?;
+            Some(DnsDiscovery::new(dns, ec.clone(), resolver.clone()))
+        })
+        .collect()
+}
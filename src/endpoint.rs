@@ -1,13 +1,34 @@
+use crate::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+use crate::config::{EndpointHealthCheckConfig, SlowStartConfig};
+use crate::events::{Event, EventKind, EventLog};
+use crate::health_state::{HealthState, HealthTracker, HealthTransition};
+use crate::outlier::{EjectionReason, Outcome, OutlierDetectionConfig, OutlierState};
+use crate::probes::Probe;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 
 #[derive(Clone)]
 pub struct Endpoint {
     pub url: String,
     pub weight: u32,
     pub max_connections: u32,
-    healthy: Arc<AtomicBool>,
+    pub labels: BTreeMap<String, String>,
+    health: Arc<HealthTracker>,
     current_connections: Arc<AtomicU32>,
+    outlier: Arc<Mutex<OutlierState>>,
+    circuit: Arc<CircuitBreaker>,
+    draining: Arc<AtomicBool>,
+    slow_start: SlowStartConfig,
+    /// When the endpoint last recovered from unhealthy, for the slow-start ramp.
+    recovered_at: Arc<Mutex<Option<Instant>>>,
+    health_check: EndpointHealthCheckConfig,
+    probe: Option<Probe>,
+    /// Models whose canary output no longer matches, with the mismatch.
+    canary_failures: Arc<Mutex<BTreeMap<String, String>>>,
+    events: Arc<EventLog>,
 }
 
 impl Endpoint {
@@ -16,38 +37,429 @@ impl Endpoint {
             url,
             weight,
             max_connections,
-            healthy: Arc::new(AtomicBool::new(true)),
+            labels: BTreeMap::new(),
+            health: Arc::new(HealthTracker::new(1, 1)),
             current_connections: Arc::new(AtomicU32::new(0)),
+            outlier: Arc::new(Mutex::new(OutlierState::default())),
+            circuit: Arc::new(CircuitBreaker::new(CircuitBreakerConfig::default())),
+            draining: Arc::new(AtomicBool::new(false)),
+            slow_start: SlowStartConfig::default(),
+            recovered_at: Arc::new(Mutex::new(None)),
+            health_check: EndpointHealthCheckConfig::default(),
+            probe: None,
+            canary_failures: Arc::new(Mutex::new(BTreeMap::new())),
+            events: Arc::new(EventLog::default()),
         }
     }
 
+    pub fn with_circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit = Arc::new(CircuitBreaker::new(config));
+        self
+    }
+
+    pub fn with_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn with_slow_start(mut self, config: SlowStartConfig) -> Self {
+        self.slow_start = config;
+        self
+    }
+
+    /// Carries over the live state of the endpoint this one replaces on reload:
+    /// connection count, health, outlier and circuit state, drain flag, canary
+    /// results and history. Only the configured settings come from `self`.
+    pub fn with_state_of(mut self, previous: &Endpoint) -> Self {
+        self.health = previous.health.clone();
+        self.current_connections = previous.current_connections.clone();
+        self.outlier = previous.outlier.clone();
+        self.circuit = previous.circuit.clone();
+        self.draining = previous.draining.clone();
+        self.recovered_at = previous.recovered_at.clone();
+        self.canary_failures = previous.canary_failures.clone();
+        self.events = previous.events.clone();
+        self
+    }
+
+    pub fn with_event_log(mut self, events: EventLog) -> Self {
+        self.events = Arc::new(events);
+        self
+    }
+
+    /// Adds an event to the endpoint's history and, unless routine, the fleet stream.
+    pub fn record_event(&self, kind: EventKind) {
+        self.events.record(&self.url, kind);
+    }
+
+    /// Up to `limit` of the most recent events, oldest first.
+    pub fn history(&self, limit: usize) -> Vec<Event> {
+        self.events.recent(limit)
+    }
+
+    pub fn with_health_check(mut self, config: EndpointHealthCheckConfig) -> Self {
+        self.health_check = config;
+        self
+    }
+
+    /// Replaces the health checker's default probe for this endpoint.
+    pub fn with_probe(mut self, probe: Probe) -> Self {
+        self.probe = Some(probe);
+        self
+    }
+
+    pub fn probe(&self) -> Option<&Probe> {
+        self.probe.as_ref()
+    }
+
+    /// Per-endpoint overrides of the health check interval and timeout.
+    pub fn health_check_config(&self) -> &EndpointHealthCheckConfig {
+        &self.health_check
+    }
+
+    pub fn with_health_thresholds(
+        mut self,
+        healthy_threshold: u32,
+        unhealthy_threshold: u32,
+    ) -> Self {
+        self.health = Arc::new(HealthTracker::new(healthy_threshold, unhealthy_threshold));
+        self
+    }
+
     pub fn is_healthy(&self) -> bool {
-        self.healthy.load(Ordering::Relaxed)
+        self.health.state().is_routable()
+    }
+
+    pub fn health_state(&self) -> HealthState {
+        self.health.state()
     }
 
-    pub fn mark_healthy(&self) {
-        self.healthy.store(true, Ordering::Relaxed);
+    pub fn last_health_transition(&self) -> Option<HealthTransition> {
+        self.health.last_transition()
     }
 
-    pub fn mark_unhealthy(&self) {
-        self.healthy.store(false, Ordering::Relaxed);
+    /// Records a health probe result against the endpoint's health state machine.
+    pub fn record_health(&self, success: bool, reason: impl Into<String>) {
+        if let Some(transition) = self.health.record(success, reason.into()) {
+            // Ramp up whenever the endpoint starts taking traffic, whether it
+            // recovered or is new and passed its first probe
+            if transition.to == HealthState::Healthy && !transition.from.is_routable() {
+                *self.recovered_at.lock().unwrap() = Some(Instant::now());
+            }
+            match transition.to {
+                HealthState::Healthy => info!(
+                    "Endpoint {} is now healthy (was {}): {}",
+                    self.url,
+                    transition.from.as_str(),
+                    transition.reason
+                ),
+                to => warn!(
+                    "Endpoint {} is now {} (was {}): {}",
+                    self.url,
+                    to.as_str(),
+                    transition.from.as_str(),
+                    transition.reason
+                ),
+            }
+            crate::metrics::record_health_transition(&self.url, transition.to);
+            self.record_event(EventKind::HealthChanged {
+                from: transition.from,
+                to: transition.to,
+                reason: transition.reason,
+            });
+        }
+    }
+
+    /// The configured weight, scaled down linearly from `initial_weight` while
+    /// the endpoint is within its slow-start window after becoming healthy.
+    pub fn effective_weight(&self) -> f64 {
+        let weight = self.weight as f64;
+        let window = Duration::from_secs(self.slow_start.window_seconds);
+        let recovered_at = *self.recovered_at.lock().unwrap();
+        match recovered_at {
+            Some(at) if at.elapsed() < window => {
+                let initial = self.slow_start.initial_weight.clamp(0.0, 1.0);
+                let progress = at.elapsed().as_secs_f64() / window.as_secs_f64();
+                weight * (initial + (1.0 - initial) * progress)
+            }
+            _ => weight,
+        }
+    }
+
+    /// Records a canary result for a model, taking the endpoint out of rotation
+    /// for that model while its output does not match.
+    pub fn record_canary(&self, model: &str, result: std::result::Result<(), String>) {
+        let mut failures = self.canary_failures.lock().unwrap();
+        match result {
+            Ok(()) => {
+                if failures.remove(model).is_some() {
+                    info!("Canary for {} on {} passes again", model, self.url);
+                    self.record_event(EventKind::CanaryRecovered {
+                        model: model.to_string(),
+                    });
+                }
+            }
+            Err(reason) => {
+                if !failures.contains_key(model) {
+                    warn!(
+                        "Canary for {} on {} failed, marking it unhealthy for that model: {}",
+                        model, self.url, reason
+                    );
+                    self.record_event(EventKind::CanaryFailed {
+                        model: model.to_string(),
+                        reason: reason.clone(),
+                    });
+                }
+                crate::metrics::record_canary_failure(&self.url, model);
+                failures.insert(model.to_string(), reason);
+            }
+        }
+    }
+
+    pub fn is_model_failing(&self, model: &str) -> bool {
+        self.canary_failures.lock().unwrap().contains_key(model)
+    }
+
+    pub fn canary_failures(&self) -> BTreeMap<String, String> {
+        self.canary_failures.lock().unwrap().clone()
+    }
+
+    /// Whether the endpoint may receive new requests: healthy, not draining, not
+    /// ejected as an outlier and with a circuit that admits traffic.
+    pub fn is_available(&self) -> bool {
+        self.is_healthy()
+            && !self.is_draining()
+            && !self.is_ejected()
+            && self.circuit.allows_request()
     }
 
-    pub fn increment_connections(&self) -> bool {
-        let current = self.current_connections.fetch_add(1, Ordering::SeqCst);
-        if current >= self.max_connections {
-            self.current_connections.fetch_sub(1, Ordering::SeqCst);
-            false
+    /// Stops new requests from being routed here while in-flight ones finish.
+    pub fn drain(&self) {
+        if !self.draining.swap(true, Ordering::SeqCst) {
+            info!(
+                "Draining endpoint {} with {} connections in flight",
+                self.url,
+                self.get_connections()
+            );
+            self.record_event(EventKind::Draining);
+        }
+    }
+
+    pub fn undrain(&self) {
+        if self.draining.swap(false, Ordering::SeqCst) {
+            info!("Endpoint {} is back in rotation", self.url);
+            self.record_event(EventKind::Undrained);
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit.state()
+    }
+
+    /// Reserves a slot through the circuit breaker for a request about to be sent.
+    pub fn try_acquire_circuit(&self) -> bool {
+        self.circuit.try_acquire()
+    }
+
+    /// Feeds a proxied request result into the circuit breaker.
+    pub fn record_circuit_result(&self, success: bool) {
+        let transition = if success {
+            self.circuit.record_success()
         } else {
-            true
+            self.circuit.record_failure()
+        };
+        if let Some(state) = transition {
+            self.log_circuit_transition(state);
+        }
+    }
+
+    /// Feeds a health probe result into the circuit breaker.
+    pub fn record_circuit_probe(&self, success: bool) {
+        if let Some(state) = self.circuit.record_probe(success) {
+            self.log_circuit_transition(state);
         }
     }
 
+    fn log_circuit_transition(&self, state: CircuitState) {
+        match state {
+            CircuitState::Open => warn!("Circuit for {} is now open", self.url),
+            _ => info!("Circuit for {} is now {}", self.url, state.as_str()),
+        }
+        crate::metrics::record_circuit_transition(&self.url, state);
+        self.record_event(EventKind::CircuitChanged { state });
+    }
+
+    pub fn is_ejected(&self) -> bool {
+        self.outlier.lock().unwrap().is_ejected()
+    }
+
+    /// Feeds a proxied request result into outlier detection, returning why
+    /// the endpoint should be ejected if it now should.
+    pub fn record_outlier_outcome(
+        &self,
+        outcome: Outcome,
+        config: &OutlierDetectionConfig,
+    ) -> Option<EjectionReason> {
+        self.outlier.lock().unwrap().record(outcome, config)
+    }
+
+    /// Takes the endpoint out of rotation as an outlier, returning for how long.
+    pub fn eject(&self, config: &OutlierDetectionConfig) -> Duration {
+        self.outlier.lock().unwrap().eject(config)
+    }
+
     pub fn decrement_connections(&self) {
-        self.current_connections.fetch_sub(1, Ordering::SeqCst);
+        let previous = self.current_connections.fetch_sub(1, Ordering::SeqCst);
+        if previous == 1 && self.is_draining() {
+            info!("Endpoint {} is drained", self.url);
+        }
     }
 
     pub fn get_connections(&self) -> u32 {
         self.current_connections.load(Ordering::Relaxed)
     }
+
+    /// Whether the endpoint is below `max_connections` and may take another request.
+    pub fn has_capacity(&self) -> bool {
+        self.get_connections() < self.max_connections
+    }
+
+    /// Counts a connection against this endpoint until the returned guard is dropped.
+    /// `max_connections` is enforced when endpoints are selected, not here.
+    pub fn track_connection(&self) -> ConnectionGuard {
+        self.current_connections.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard {
+            endpoint: self.clone(),
+        }
+    }
+}
+
+pub struct ConnectionGuard {
+    endpoint: Endpoint,
+}
+
+impl ConnectionGuard {
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.endpoint.decrement_connections();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint() -> Endpoint {
+        let endpoint = Endpoint::new("http://gpu1:11434".to_string(), 4, 10);
+        endpoint.record_health(true, "test setup");
+        endpoint
+    }
+
+    fn event_names(endpoint: &Endpoint) -> Vec<&'static str> {
+        endpoint
+            .history(100)
+            .iter()
+            .map(|event| event.kind.name())
+            .collect()
+    }
+
+    #[test]
+    fn draining_takes_the_endpoint_out_of_rotation() {
+        let endpoint = endpoint();
+        let _in_flight = endpoint.track_connection();
+        endpoint.drain();
+        endpoint.drain();
+        assert!(endpoint.is_draining());
+        assert!(endpoint.is_healthy());
+        assert!(!endpoint.is_available());
+        assert_eq!(endpoint.get_connections(), 1);
+
+        endpoint.undrain();
+        endpoint.undrain();
+        assert!(endpoint.is_available());
+        assert_eq!(
+            event_names(&endpoint),
+            ["health_changed", "draining", "undrained"]
+        );
+    }
+
+    #[test]
+    fn drain_state_survives_a_reload() {
+        let endpoint = endpoint();
+        endpoint.drain();
+        let reloaded = Endpoint::new(endpoint.url.clone(), 8, 10).with_state_of(&endpoint);
+        assert!(reloaded.is_draining());
+        reloaded.undrain();
+        assert!(!endpoint.is_draining());
+    }
+
+    fn slow_start(window_seconds: u64) -> Endpoint {
+        Endpoint::new("http://gpu1:11434".to_string(), 10, 10)
+            .with_health_thresholds(1, 3)
+            .with_slow_start(SlowStartConfig {
+                window_seconds,
+                initial_weight: 0.2,
+            })
+    }
+
+    fn recovered_ago(endpoint: &Endpoint, seconds: u64) {
+        *endpoint.recovered_at.lock().unwrap() = Some(
+            Instant::now()
+                .checked_sub(Duration::from_secs(seconds))
+                .unwrap(),
+        );
+    }
+
+    fn assert_weight(endpoint: &Endpoint, expected: f64) {
+        let weight = endpoint.effective_weight();
+        assert!((weight - expected).abs() < 0.05, "weight {}", weight);
+    }
+
+    #[test]
+    fn slow_start_ramps_the_weight_up_linearly() {
+        let endpoint = slow_start(100);
+        assert_weight(&endpoint, 10.0);
+
+        endpoint.record_health(true, "first probe");
+        assert_weight(&endpoint, 2.0);
+        recovered_ago(&endpoint, 50);
+        assert_weight(&endpoint, 6.0);
+        recovered_ago(&endpoint, 100);
+        assert_weight(&endpoint, 10.0);
+    }
+
+    #[test]
+    fn only_recovering_from_unhealthy_restarts_the_ramp() {
+        let endpoint = slow_start(100);
+        endpoint.record_health(true, "first probe");
+        recovered_ago(&endpoint, 100);
+
+        endpoint.record_health(false, "probe failed");
+        assert_eq!(endpoint.health_state(), HealthState::Degraded);
+        endpoint.record_health(true, "probe passed");
+        assert_weight(&endpoint, 10.0);
+
+        for _ in 0..3 {
+            endpoint.record_health(false, "probe failed");
+        }
+        assert_eq!(endpoint.health_state(), HealthState::Unhealthy);
+        endpoint.record_health(true, "probe passed");
+        assert_weight(&endpoint, 2.0);
+    }
+
+    #[test]
+    fn a_zero_window_disables_slow_start() {
+        let endpoint = slow_start(0);
+        endpoint.record_health(true, "first probe");
+        assert_weight(&endpoint, 10.0);
+    }
 }
@@ -1,3 +1,4 @@
+use crate::priority::Priority;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -5,15 +6,54 @@ pub enum LoadBalancerError {
     #[error("No healthy endpoints available")]
     NoHealthyEndpoints,
 
+    #[error("No endpoint matches route {0}")]
+    NoMatchingEndpoints(String),
+
+    #[error("Every matching endpoint is at max_connections")]
+    AtCapacity,
+
+    #[error("Server overloaded, {0} priority request shed")]
+    LoadShed(Priority),
+
+    #[error("Upstream timed out: {0}")]
+    Timeout(String),
+
+    #[error("Unknown endpoint: {0}")]
+    EndpointNotFound(String),
+
+    #[error("Endpoint already exists: {0}")]
+    EndpointExists(String),
+
+    #[error("Invalid request: {0}")]
+    BadRequest(String),
+
+    #[error("Self-registration is disabled")]
+    RegistrationDisabled,
+
+    #[error("Missing or wrong registration token")]
+    Unauthorized,
+
+    #[error("Not registered: {0}")]
+    NotRegistered(String),
+
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
+    #[error("Invalid configuration:\n{0}")]
+    InvalidConfig(crate::validation::ValidationReport),
+
     #[error("HTTP error: {0}")]
     HttpError(#[from] reqwest::Error),
 
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
+    #[error("DNS lookup failed: {0}")]
+    DnsError(String),
+
+    #[error("Endpoint discovery failed: {0}")]
+    DiscoveryError(String),
+
     #[error("Health check failed: {0}")]
     HealthCheckError(String),
 
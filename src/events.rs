@@ -0,0 +1,228 @@
+use crate::circuit_breaker::CircuitState;
+use crate::health_state::HealthState;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EventsConfig {
+    /// Events kept in memory per endpoint.
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    /// Fleet events buffered for each stream subscriber before it starts missing some.
+    #[serde(default = "default_stream_buffer")]
+    pub stream_buffer: usize,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            history_size: default_history_size(),
+            stream_buffer: default_stream_buffer(),
+        }
+    }
+}
+
+fn default_history_size() -> usize {
+    100
+}
+
+fn default_stream_buffer() -> usize {
+    256
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    /// Unix time in milliseconds.
+    pub at: u64,
+    pub endpoint: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    HealthChanged {
+        from: HealthState,
+        to: HealthState,
+        reason: String,
+    },
+    Probe {
+        success: bool,
+        latency_ms: u64,
+        error: Option<String>,
+    },
+    CircuitChanged {
+        state: CircuitState,
+    },
+    Ejected {
+        reason: String,
+        seconds: u64,
+    },
+    Draining,
+    Undrained,
+    CanaryFailed {
+        model: String,
+        reason: String,
+    },
+    CanaryRecovered {
+        model: String,
+    },
+    ModelPulled {
+        model: String,
+    },
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::HealthChanged { .. } => "health_changed",
+            EventKind::Probe { .. } => "probe",
+            EventKind::CircuitChanged { .. } => "circuit_changed",
+            EventKind::Ejected { .. } => "ejected",
+            EventKind::Draining => "draining",
+            EventKind::Undrained => "undrained",
+            EventKind::CanaryFailed { .. } => "canary_failed",
+            EventKind::CanaryRecovered { .. } => "canary_recovered",
+            EventKind::ModelPulled { .. } => "model_pulled",
+        }
+    }
+
+    /// Whether the event goes out on the fleet stream. Routine probe results
+    /// only land in the endpoint's history.
+    fn is_fleet_event(&self) -> bool {
+        !matches!(self, EventKind::Probe { .. })
+    }
+}
+
+/// Bounded history of one endpoint's events, feeding fleet events to a shared stream.
+#[derive(Debug)]
+pub struct EventLog {
+    events: Mutex<VecDeque<Event>>,
+    capacity: usize,
+    stream: broadcast::Sender<Event>,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize, stream: broadcast::Sender<Event>) -> Self {
+        Self {
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            stream,
+        }
+    }
+
+    pub fn record(&self, endpoint: &str, kind: EventKind) {
+        let event = Event {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            endpoint: endpoint.to_string(),
+            kind,
+        };
+
+        if event.kind.is_fleet_event() {
+            // No subscribers is not an error
+            let _ = self.stream.send(event.clone());
+        }
+
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= self.capacity {
+            events.pop_front();
+        }
+        if self.capacity > 0 {
+            events.push_back(event);
+        }
+    }
+
+    /// The most recent events, oldest first.
+    pub fn recent(&self, limit: usize) -> Vec<Event> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .skip(events.len().saturating_sub(limit))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new(default_history_size(), broadcast::channel(1).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe() -> EventKind {
+        EventKind::Probe {
+            success: true,
+            latency_ms: 5,
+            error: None,
+        }
+    }
+
+    fn models(events: &[Event]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match &event.kind {
+                EventKind::ModelPulled { model } => Some(model.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn pulled(model: &str) -> EventKind {
+        EventKind::ModelPulled {
+            model: model.to_string(),
+        }
+    }
+
+    #[test]
+    fn history_keeps_the_latest_events() {
+        let log = EventLog::new(3, broadcast::channel(16).0);
+        for model in ["a", "b", "c", "d", "e"] {
+            log.record("http://gpu1:11434", pulled(model));
+        }
+        assert_eq!(models(&log.recent(10)), ["c", "d", "e"]);
+        assert_eq!(models(&log.recent(2)), ["d", "e"]);
+        assert!(log.recent(0).is_empty());
+    }
+
+    #[test]
+    fn zero_capacity_keeps_no_history_but_still_streams() {
+        let (stream, mut receiver) = broadcast::channel(16);
+        let log = EventLog::new(0, stream);
+        log.record("http://gpu1:11434", EventKind::Draining);
+        assert!(log.recent(10).is_empty());
+        assert_eq!(receiver.try_recv().unwrap().kind.name(), "draining");
+    }
+
+    #[test]
+    fn probes_stay_out_of_the_fleet_stream() {
+        let (stream, mut receiver) = broadcast::channel(16);
+        let log = EventLog::new(10, stream);
+        log.record("http://gpu1:11434", probe());
+        log.record("http://gpu1:11434", EventKind::Undrained);
+
+        let names: Vec<_> = log.recent(10).iter().map(|e| e.kind.name()).collect();
+        assert_eq!(names, ["probe", "undrained"]);
+        let streamed = receiver.try_recv().unwrap();
+        assert_eq!(streamed.endpoint, "http://gpu1:11434");
+        assert_eq!(streamed.kind.name(), "undrained");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn recording_without_subscribers_is_fine() {
+        let log = EventLog::new(10, broadcast::channel(1).0);
+        log.record("http://gpu1:11434", EventKind::Draining);
+        assert_eq!(log.recent(10).len(), 1);
+    }
+}
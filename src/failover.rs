@@ -0,0 +1,43 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StreamFailoverConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Times a single streamed answer may move to another endpoint.
+    #[serde(default = "default_max_failovers")]
+    pub max_failovers: u32,
+}
+
+impl Default for StreamFailoverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_failovers: default_max_failovers(),
+        }
+    }
+}
+
+fn default_max_failovers() -> u32 {
+    1
+}
+
+/// Whether a request body is a streamed chat that can be continued on another endpoint.
+pub fn is_failover_candidate(path: &str, request: &serde_json::Value) -> bool {
+    path == "/api/chat"
+        && request.get("messages").is_some_and(|m| m.is_array())
+        && request.get("stream").and_then(|s| s.as_bool()) != Some(false)
+}
+
+/// The original chat request with the answer streamed so far appended as an
+/// assistant message, which Ollama continues rather than starting over.
+pub fn continuation_request(request: &serde_json::Value, partial: &str) -> serde_json::Value {
+    let mut request = request.clone();
+    if let Some(messages) = request.get_mut("messages").and_then(|m| m.as_array_mut()) {
+        messages.push(serde_json::json!({
+            "role": "assistant",
+            "content": partial,
+        }));
+    }
+    request
+}
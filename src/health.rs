@@ -1,16 +1,30 @@
 use crate::endpoint::Endpoint;
-use crate::error::Result;
+use crate::error::{LoadBalancerError, Result};
+use crate::events::EventKind;
+use crate::health_state::HealthState;
 use crate::model_manager::ModelManager;
 use async_trait::async_trait;
-use std::time::Duration;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use rand::Rng;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 use tokio::time;
 use tracing::{info, warn};
 
 #[async_trait]
-pub trait HealthCheck {
+pub trait HealthCheck: Send + Sync {
     async fn check_health(&self, endpoint: &Endpoint) -> Result<bool>;
 }
 
+#[async_trait]
+impl<T: HealthCheck + ?Sized> HealthCheck for Arc<T> {
+    async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
+        (**self).check_health(endpoint).await
+    }
+}
+
 pub struct HttpHealthCheck {
     client: reqwest::Client,
     required_model: String,
@@ -18,14 +32,10 @@ pub struct HttpHealthCheck {
 }
 
 impl HttpHealthCheck {
-    pub fn new(timeout: Duration, required_model: String) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(timeout)
-            .build()
-            .expect("Failed to create HTTP client");
-
+    /// Probe timeouts are enforced by `HealthChecker`, per endpoint.
+    pub fn new(required_model: String) -> Self {
         Self {
-            client,
+            client: reqwest::Client::new(),
             required_model,
             model_manager: ModelManager::new(),
         }
@@ -42,55 +52,33 @@ impl HttpHealthCheck {
 impl HealthCheck for HttpHealthCheck {
     async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
         // First check basic connectivity
-        let basic_health = match self.client.get(&endpoint.url).send().await {
-            Ok(response) => {
-                let is_success = response.status().is_success();
-                if !is_success {
-                    warn!(
-                        "Basic health check failed for {}: status {}",
-                        endpoint.url,
-                        response.status()
-                    );
-                }
-                is_success
+        match self.client.get(&endpoint.url).send().await {
+            Ok(response) if !response.status().is_success() => {
+                return Err(LoadBalancerError::HealthCheckError(format!(
+                    "basic check returned status {}",
+                    response.status()
+                )));
             }
+            Ok(_) => {}
             Err(e) => {
-                warn!("Basic health check failed for {}: {}", endpoint.url, e);
-                false
+                return Err(LoadBalancerError::HealthCheckError(format!(
+                    "basic check failed: {}",
+                    e
+                )));
             }
-        };
-
-        if !basic_health {
-            endpoint.mark_unhealthy();
-            return Ok(false);
         }
 
         // Then verify model availability
         match self.verify_model(endpoint).await {
-            Ok(true) => {
-                // info!(
-                //     "Health check passed for {} with model {}",
-                //     endpoint.url, self.required_model
-                // );
-                endpoint.mark_healthy();
-                Ok(true)
-            }
-            Ok(false) => {
-                warn!(
-                    "Health check failed for {}: required model {} not found",
-                    endpoint.url, self.required_model
-                );
-                endpoint.mark_unhealthy();
-                Ok(false)
-            }
-            Err(e) => {
-                warn!(
-                    "Model verification failed for {} with model {}: {}",
-                    endpoint.url, self.required_model, e
-                );
-                endpoint.mark_unhealthy();
-                Ok(false)
-            }
+            Ok(true) => Ok(true),
+            Ok(false) => Err(LoadBalancerError::HealthCheckError(format!(
+                "required model {} not found",
+                self.required_model
+            ))),
+            Err(e) => Err(LoadBalancerError::HealthCheckError(format!(
+                "model verification for {} failed: {}",
+                self.required_model, e
+            ))),
         }
     }
 }
@@ -98,6 +86,7 @@ impl HealthCheck for HttpHealthCheck {
 pub struct HealthChecker {
     checker: Box<dyn HealthCheck + Send + Sync>,
     config: crate::config::HealthCheckConfig,
+    stop: watch::Sender<bool>,
 }
 
 impl HealthChecker {
@@ -105,40 +94,235 @@ impl HealthChecker {
         checker: Box<dyn HealthCheck + Send + Sync>,
         config: crate::config::HealthCheckConfig,
     ) -> Self {
-        Self { checker, config }
+        Self {
+            checker,
+            config,
+            stop: watch::channel(false).0,
+        }
     }
 
-    pub async fn check_single_endpoint(&self, endpoint: &Endpoint) -> Result<bool> {
-        self.checker.check_health(endpoint).await
+    /// Ends the health check loop once the current round of probes completes.
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
     }
 
-    pub async fn start_health_checks(&self, endpoints: Vec<Endpoint>) {
-        let interval = Duration::from_secs(self.config.interval_seconds);
-        let mut ticker = time::interval(interval);
+    pub async fn check_single_endpoint(&self, endpoint: &Endpoint) -> Result<bool> {
+        let timeout = Duration::from_secs(
+            endpoint
+                .health_check_config()
+                .timeout_seconds
+                .unwrap_or(self.config.timeout_seconds),
+        );
+        let checker = match endpoint.probe() {
+            Some(probe) => probe.as_ref(),
+            None => self.checker.as_ref(),
+        };
+        let started = Instant::now();
+        let result = match time::timeout(timeout, checker.check_health(endpoint)).await {
+            Ok(result) => result,
+            Err(_) => Err(LoadBalancerError::HealthCheckError(format!(
+                "no answer within {}s",
+                timeout.as_secs()
+            ))),
+        };
+        endpoint.record_event(EventKind::Probe {
+            success: matches!(result, Ok(true)),
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: match &result {
+                Ok(true) => None,
+                Ok(false) => Some("reported unhealthy".to_string()),
+                Err(e) => Some(e.to_string()),
+            },
+        });
+        match &result {
+            Ok(true) => endpoint.record_health(true, "health check passed"),
+            Ok(false) => endpoint.record_health(false, "health check reported unhealthy"),
+            Err(e) => endpoint.record_health(false, e.to_string()),
+        }
+        endpoint.record_circuit_probe(matches!(result, Ok(true)));
+        result
+    }
 
+    /// Probes every endpoint on its own schedule, so a slow endpoint never holds
+    /// up the others. Endpoints added to or removed from `endpoints` start and
+    /// stop being probed as the list changes. Returns once `stop` is called.
+    pub async fn start_health_checks(&self, mut endpoints: watch::Receiver<Arc<Vec<Endpoint>>>) {
         info!(
-            "Starting health check loop with interval of {} seconds",
+            "Starting health checks for {} endpoints with interval of {} seconds",
+            endpoints.borrow().len(),
             self.config.interval_seconds
         );
 
+        let mut stop = self.stop.subscribe();
+        let mut running = HashSet::new();
+        let mut checks = FuturesUnordered::new();
         loop {
-            ticker.tick().await;
-            for endpoint in &endpoints {
-                // info!("Performing health check for {}", endpoint.url);
-                match self.check_single_endpoint(endpoint).await {
-                    Ok(is_healthy) => {
-                        if is_healthy {
-                            // info!("Endpoint {} is healthy", endpoint.url);
-                        } else {
-                            warn!("Endpoint {} is unhealthy", endpoint.url);
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Health check failed for {}: {}", endpoint.url, e);
-                        endpoint.mark_unhealthy();
+            let urls: Vec<String> = endpoints
+                .borrow_and_update()
+                .iter()
+                .map(|endpoint| endpoint.url.clone())
+                .collect();
+            for url in urls {
+                if running.insert(url.clone()) {
+                    checks.push(self.run_checks(url, endpoints.clone()));
+                }
+            }
+
+            tokio::select! {
+                changed = endpoints.changed() => {
+                    if changed.is_err() {
+                        break;
                     }
                 }
+                Some(url) = checks.next() => {
+                    running.remove(&url);
+                }
+                _ = stop.wait_for(|stopped| *stopped) => break,
+            }
+        }
+
+        // Every loop sees the stop signal and returns after its current probe
+        while checks.next().await.is_some() {}
+
+        info!("Health check loop stopped");
+    }
+
+    /// Probes one endpoint until it is removed from the list or checks stop.
+    async fn run_checks(
+        &self,
+        url: String,
+        endpoints: watch::Receiver<Arc<Vec<Endpoint>>>,
+    ) -> String {
+        let mut stop = self.stop.subscribe();
+        let mut next = self.jitter();
+
+        loop {
+            tokio::select! {
+                _ = time::sleep(next) => {}
+                _ = stop.wait_for(|stopped| *stopped) => return url,
+            }
+
+            // Look the endpoint up each time so reloaded settings take effect
+            let current = endpoints.borrow().iter().find(|e| e.url == url).cloned();
+            let Some(endpoint) = current else {
+                info!("Stopped health checks for removed endpoint {}", url);
+                return url;
+            };
+
+            match self.check_single_endpoint(&endpoint).await {
+                Ok(true) => {}
+                Ok(false) => warn!("Endpoint {} is unhealthy", endpoint.url),
+                Err(e) => warn!("Health check failed for {}: {}", endpoint.url, e),
+            }
+
+            next = self.interval_for(&endpoint) + self.jitter();
+        }
+    }
+
+    fn interval_for(&self, endpoint: &Endpoint) -> Duration {
+        let interval = endpoint
+            .health_check_config()
+            .interval_seconds
+            .unwrap_or(self.config.interval_seconds);
+        let interval = match self.config.unhealthy_interval_seconds {
+            Some(faster) if endpoint.health_state() != HealthState::Healthy => interval.min(faster),
+            _ => interval,
+        };
+        Duration::from_secs(interval)
+    }
+
+    fn jitter(&self) -> Duration {
+        if self.config.jitter_ms == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(0..=self.config.jitter_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EndpointHealthCheckConfig, HealthCheckConfig};
+
+    /// Passes for every endpoint except those it never answers for.
+    struct Stalls(&'static str);
+
+    #[async_trait]
+    impl HealthCheck for Stalls {
+        async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
+            if endpoint.url == self.0 {
+                std::future::pending::<()>().await;
             }
+            Ok(true)
         }
     }
+
+    fn checker(config: HealthCheckConfig) -> HealthChecker {
+        HealthChecker::new(Box::new(Stalls("http://slow:11434")), config)
+    }
+
+    fn endpoint(url: &str) -> Endpoint {
+        Endpoint::new(url.to_string(), 1, 10).with_health_thresholds(1, 2)
+    }
+
+    #[test]
+    fn jitter_stays_within_its_bound() {
+        assert_eq!(
+            checker(HealthCheckConfig::default()).jitter(),
+            Duration::ZERO
+        );
+
+        let checker = checker(HealthCheckConfig {
+            jitter_ms: 20,
+            ..HealthCheckConfig::default()
+        });
+        for _ in 0..100 {
+            assert!(checker.jitter() <= Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn endpoints_that_are_not_healthy_are_probed_sooner() {
+        let checker = checker(HealthCheckConfig {
+            interval_seconds: 30,
+            unhealthy_interval_seconds: Some(5),
+            ..HealthCheckConfig::default()
+        });
+        let endpoint = endpoint("http://gpu1:11434");
+        assert_eq!(checker.interval_for(&endpoint), Duration::from_secs(5));
+        endpoint.record_health(true, "probe passed");
+        assert_eq!(checker.interval_for(&endpoint), Duration::from_secs(30));
+        endpoint.record_health(false, "probe failed");
+        assert_eq!(endpoint.health_state(), HealthState::Degraded);
+        assert_eq!(checker.interval_for(&endpoint), Duration::from_secs(5));
+
+        let quick = endpoint.with_health_check(EndpointHealthCheckConfig {
+            interval_seconds: Some(2),
+            ..EndpointHealthCheckConfig::default()
+        });
+        assert_eq!(checker.interval_for(&quick), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn a_stalled_probe_does_not_hold_up_the_others() {
+        let checker = Arc::new(checker(HealthCheckConfig {
+            interval_seconds: 60,
+            timeout_seconds: 1,
+            ..HealthCheckConfig::default()
+        }));
+        let slow = endpoint("http://slow:11434");
+        let fast = endpoint("http://fast:11434");
+        let (_endpoints, receiver) = watch::channel(Arc::new(vec![slow.clone(), fast.clone()]));
+        let running = checker.clone();
+        let task = tokio::spawn(async move { running.start_health_checks(receiver).await });
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(fast.health_state(), HealthState::Healthy);
+        assert_eq!(slow.health_state(), HealthState::Unknown);
+
+        // Stopping waits for the stalled probe to time out
+        checker.stop();
+        task.await.unwrap();
+        assert_eq!(slow.health_state(), HealthState::Unhealthy);
+    }
 }
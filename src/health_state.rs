@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthState {
+    /// No probe has completed yet.
+    Unknown,
+    Healthy,
+    /// Healthy endpoint that has started failing probes but not yet crossed `unhealthy_threshold`.
+    Degraded,
+    Unhealthy,
+}
+
+impl HealthState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthState::Unknown => "unknown",
+            HealthState::Healthy => "healthy",
+            HealthState::Degraded => "degraded",
+            HealthState::Unhealthy => "unhealthy",
+        }
+    }
+
+    /// Whether traffic may be routed to an endpoint in this state.
+    pub fn is_routable(&self) -> bool {
+        matches!(self, HealthState::Healthy | HealthState::Degraded)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthTransition {
+    pub from: HealthState,
+    pub to: HealthState,
+    pub at: SystemTime,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+struct TrackerState {
+    state: HealthState,
+    consecutive_successes: u32,
+    consecutive_failures: u32,
+    last_transition: Option<HealthTransition>,
+}
+
+/// Health state machine that only changes state after `healthy_threshold`
+/// consecutive passing or `unhealthy_threshold` consecutive failing probes.
+/// An endpoint with no history is decided by its first probe.
+#[derive(Debug)]
+pub struct HealthTracker {
+    healthy_threshold: u32,
+    unhealthy_threshold: u32,
+    inner: Mutex<TrackerState>,
+}
+
+impl HealthTracker {
+    pub fn new(healthy_threshold: u32, unhealthy_threshold: u32) -> Self {
+        Self {
+            healthy_threshold: healthy_threshold.max(1),
+            unhealthy_threshold: unhealthy_threshold.max(1),
+            inner: Mutex::new(TrackerState {
+                state: HealthState::Unknown,
+                consecutive_successes: 0,
+                consecutive_failures: 0,
+                last_transition: None,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> HealthState {
+        self.inner.lock().unwrap().state
+    }
+
+    pub fn last_transition(&self) -> Option<HealthTransition> {
+        self.inner.lock().unwrap().last_transition.clone()
+    }
+
+    /// Records a probe result. Returns the transition if the state changed.
+    pub fn record(&self, success: bool, reason: String) -> Option<HealthTransition> {
+        let mut inner = self.inner.lock().unwrap();
+        if success {
+            inner.consecutive_successes += 1;
+            inner.consecutive_failures = 0;
+        } else {
+            inner.consecutive_failures += 1;
+            inner.consecutive_successes = 0;
+        }
+
+        let next = match (inner.state, success) {
+            (HealthState::Unknown, true) => HealthState::Healthy,
+            (HealthState::Unknown, false) => HealthState::Unhealthy,
+            (HealthState::Healthy, true) => HealthState::Healthy,
+            (HealthState::Degraded, true) => HealthState::Healthy,
+            (HealthState::Healthy | HealthState::Degraded, false) => {
+                if inner.consecutive_failures >= self.unhealthy_threshold {
+                    HealthState::Unhealthy
+                } else {
+                    HealthState::Degraded
+                }
+            }
+            (HealthState::Unhealthy, true) => {
+                if inner.consecutive_successes >= self.healthy_threshold {
+                    HealthState::Healthy
+                } else {
+                    HealthState::Unhealthy
+                }
+            }
+            (HealthState::Unhealthy, false) => HealthState::Unhealthy,
+        };
+
+        if next == inner.state {
+            return None;
+        }
+
+        let transition = HealthTransition {
+            from: inner.state,
+            to: next,
+            at: SystemTime::now(),
+            reason,
+        };
+        inner.state = next;
+        inner.last_transition = Some(transition.clone());
+        Some(transition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(tracker: &HealthTracker, success: bool) -> Option<HealthState> {
+        tracker
+            .record(success, "probe".to_string())
+            .map(|transition| transition.to)
+    }
+
+    #[test]
+    fn first_probe_decides_unknown_endpoints() {
+        let tracker = HealthTracker::new(3, 3);
+        assert_eq!(tracker.state(), HealthState::Unknown);
+        assert_eq!(record(&tracker, true), Some(HealthState::Healthy));
+
+        let tracker = HealthTracker::new(3, 3);
+        assert_eq!(record(&tracker, false), Some(HealthState::Unhealthy));
+        let transition = tracker.last_transition().unwrap();
+        assert_eq!(transition.from, HealthState::Unknown);
+        assert_eq!(transition.reason, "probe");
+    }
+
+    #[test]
+    fn failures_degrade_before_the_threshold() {
+        let tracker = HealthTracker::new(2, 3);
+        record(&tracker, true);
+        assert_eq!(record(&tracker, false), Some(HealthState::Degraded));
+        assert!(tracker.state().is_routable());
+        assert_eq!(record(&tracker, false), None);
+        assert_eq!(record(&tracker, false), Some(HealthState::Unhealthy));
+        assert!(!tracker.state().is_routable());
+    }
+
+    #[test]
+    fn a_success_clears_degraded() {
+        let tracker = HealthTracker::new(2, 3);
+        record(&tracker, true);
+        record(&tracker, false);
+        record(&tracker, false);
+        assert_eq!(record(&tracker, true), Some(HealthState::Healthy));
+        // The failure count starts over
+        assert_eq!(record(&tracker, false), Some(HealthState::Degraded));
+        assert_eq!(record(&tracker, false), None);
+    }
+
+    #[test]
+    fn recovery_needs_consecutive_successes() {
+        let tracker = HealthTracker::new(2, 1);
+        record(&tracker, false);
+        assert_eq!(record(&tracker, true), None);
+        assert_eq!(record(&tracker, false), None);
+        assert_eq!(record(&tracker, true), None);
+        assert_eq!(record(&tracker, true), Some(HealthState::Healthy));
+    }
+
+    #[test]
+    fn zero_thresholds_act_on_one_probe() {
+        let tracker = HealthTracker::new(0, 0);
+        record(&tracker, true);
+        assert_eq!(record(&tracker, false), Some(HealthState::Unhealthy));
+        assert_eq!(record(&tracker, true), Some(HealthState::Healthy));
+    }
+}
@@ -3,6 +3,7 @@ use crate::error::{LoadBalancerError, Result};
 use crate::strategy::LoadBalancingStrategy;
 use async_trait::async_trait;
 
+#[derive(Default)]
 pub struct LeastConnections;
 
 impl LeastConnections {
@@ -15,15 +16,18 @@ impl LeastConnections {
 impl LoadBalancingStrategy for LeastConnections {
     async fn next_endpoint<'a>(&self, endpoints: &'a [Endpoint]) -> Result<&'a Endpoint> {
         let healthy_endpoints: Vec<&'a Endpoint> =
-            endpoints.iter().filter(|e| e.is_healthy()).collect();
+            endpoints.iter().filter(|e| e.is_available()).collect();
 
         if healthy_endpoints.is_empty() {
             return Err(LoadBalancerError::NoHealthyEndpoints);
         }
 
+        // Connections per unit of weight, counting the one about to be added so a
+        // ramping endpoint with no connections does not win every pick
+        let load = |e: &Endpoint| (e.get_connections() as f64 + 1.0) / e.effective_weight();
         healthy_endpoints
             .into_iter()
-            .min_by_key(|e| e.get_connections())
+            .min_by(|a, b| load(a).total_cmp(&load(b)))
             .ok_or(LoadBalancerError::NoHealthyEndpoints)
     }
 }
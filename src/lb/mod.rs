@@ -5,3 +5,6 @@ mod round_robin;
 pub use least_conn::LeastConnections;
 pub use random::RandomStrategy;
 pub use round_robin::RoundRobin;
+
+/// Names accepted by the `strategy` setting.
+pub const STRATEGIES: &[&str] = &["round_robin", "least_connections", "random"];
@@ -4,6 +4,7 @@ use crate::strategy::LoadBalancingStrategy;
 use async_trait::async_trait;
 use rand::Rng;
 
+#[derive(Default)]
 pub struct RandomStrategy;
 
 impl RandomStrategy {
@@ -16,14 +17,26 @@ impl RandomStrategy {
 impl LoadBalancingStrategy for RandomStrategy {
     async fn next_endpoint<'a>(&self, endpoints: &'a [Endpoint]) -> Result<&'a Endpoint> {
         let healthy_endpoints: Vec<&'a Endpoint> =
-            endpoints.iter().filter(|e| e.is_healthy()).collect();
+            endpoints.iter().filter(|e| e.is_available()).collect();
 
         if healthy_endpoints.is_empty() {
             return Err(LoadBalancerError::NoHealthyEndpoints);
         }
 
         let mut rng = rand::thread_rng();
-        let index = rng.gen_range(0..healthy_endpoints.len());
-        Ok(healthy_endpoints[index])
+        let total: f64 = healthy_endpoints.iter().map(|e| e.effective_weight()).sum();
+        if total <= 0.0 {
+            let index = rng.gen_range(0..healthy_endpoints.len());
+            return Ok(healthy_endpoints[index]);
+        }
+
+        let mut pick = rng.gen_range(0.0..total);
+        for endpoint in &healthy_endpoints {
+            pick -= endpoint.effective_weight();
+            if pick < 0.0 {
+                return Ok(endpoint);
+            }
+        }
+        Ok(healthy_endpoints[healthy_endpoints.len() - 1])
     }
 }
@@ -2,16 +2,21 @@ use crate::endpoint::Endpoint;
 use crate::error::{LoadBalancerError, Result};
 use crate::strategy::LoadBalancingStrategy;
 use async_trait::async_trait;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::sync::Mutex;
 
+/// Smooth weighted round robin: every endpoint gains its effective weight per
+/// pick and the one furthest ahead is chosen, spreading heavier endpoints evenly
+/// through the rotation instead of in bursts.
+#[derive(Default)]
 pub struct RoundRobin {
-    current: AtomicUsize,
+    current: Mutex<HashMap<String, f64>>,
 }
 
 impl RoundRobin {
     pub fn new() -> Self {
         Self {
-            current: AtomicUsize::new(0),
+            current: Mutex::new(HashMap::new()),
         }
     }
 }
@@ -20,14 +25,36 @@ impl RoundRobin {
 impl LoadBalancingStrategy for RoundRobin {
     async fn next_endpoint<'a>(&self, endpoints: &'a [Endpoint]) -> Result<&'a Endpoint> {
         let healthy_endpoints: Vec<&'a Endpoint> =
-            endpoints.iter().filter(|e| e.is_healthy()).collect();
+            endpoints.iter().filter(|e| e.is_available()).collect();
 
         if healthy_endpoints.is_empty() {
             return Err(LoadBalancerError::NoHealthyEndpoints);
         }
 
-        let current = self.current.fetch_add(1, Ordering::SeqCst);
-        let index = current % healthy_endpoints.len();
-        Ok(healthy_endpoints[index])
+        let mut current = self.current.lock().unwrap();
+        let mut total = 0.0;
+        let mut selected: Option<(&'a Endpoint, f64)> = None;
+        for endpoint in healthy_endpoints {
+            let weight = endpoint.effective_weight();
+            let score = current.entry(endpoint.url.clone()).or_insert(0.0);
+            *score += weight;
+            total += weight;
+            if !matches!(selected, Some((_, best)) if *score <= best) {
+                selected = Some((endpoint, *score));
+            }
+        }
+
+        let (endpoint, _) = selected.ok_or(LoadBalancerError::NoHealthyEndpoints)?;
+        if let Some(score) = current.get_mut(&endpoint.url) {
+            *score -= total;
+        }
+        Ok(endpoint)
+    }
+
+    fn endpoints_removed(&self, urls: &[String]) {
+        let mut current = self.current.lock().unwrap();
+        for url in urls {
+            current.remove(url);
+        }
     }
 }
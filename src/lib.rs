@@ -1,27 +1,146 @@
+pub mod admin;
+pub mod canary;
+pub mod circuit_breaker;
+pub mod cli;
 pub mod config;
+pub mod ctl;
+pub mod discovery;
+pub mod dns;
 pub mod endpoint;
 pub mod error;
+pub mod events;
+pub mod failover;
 pub mod health;
+pub mod health_state;
 pub mod lb;
 pub mod metrics;
 pub mod model_manager;
+pub mod outlier;
+pub mod priority;
+pub mod probes;
+pub mod proxy_stream;
+pub mod registration;
+pub mod reload;
+pub mod retry;
+pub mod routing;
+pub mod server;
 pub mod strategy;
+pub mod timeouts;
+pub mod validation;
 
+pub use circuit_breaker::{CircuitBreakerConfig, CircuitState};
 pub use config::Config;
 pub use endpoint::Endpoint;
 pub use error::{LoadBalancerError, Result};
 pub use health::{HealthCheck, HealthChecker};
+pub use health_state::HealthState;
 pub use metrics::Metrics;
+pub use outlier::{Outcome, OutlierDetectionConfig};
+pub use priority::{Priority, PriorityConfig};
+pub use retry::RetryBudget;
+pub use routing::{Route, RoutingConfig};
 pub use strategy::LoadBalancingStrategy;
 
-use std::sync::Arc;
-use tracing::{info, warn};
+use events::{EventKind, EventLog};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+const ADMISSION_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// URLs of the endpoints a reload added, removed or reconfigured.
+#[derive(Debug, Default, Serialize)]
+pub struct EndpointChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl EndpointChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+}
+
+/// Builds an endpoint with its overrides and probe, the way the load balancer does.
+pub(crate) fn build_endpoint(
+    config: &Config,
+    ec: &config::EndpointConfig,
+    events: &broadcast::Sender<events::Event>,
+) -> Endpoint {
+    let endpoint = Endpoint::new(ec.url.clone(), ec.weight, ec.max_connections)
+        .with_health_thresholds(
+            config.health_check.healthy_threshold,
+            config.health_check.unhealthy_threshold,
+        )
+        .with_circuit_breaker(config.circuit_breaker.clone())
+        .with_slow_start(config.slow_start.clone())
+        .with_health_check(ec.health_check.clone())
+        .with_labels(ec.labels.clone())
+        .with_event_log(EventLog::new(config.events.history_size, events.clone()));
+    match &ec.health_check.probe {
+        Some(probe) => endpoint.with_probe(probe.build(&config.required_model)),
+        None => endpoint,
+    }
+}
+
+/// New limits for an endpoint; unset fields keep their current value.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct EndpointUpdate {
+    pub weight: Option<u32>,
+    pub max_connections: Option<u32>,
+}
+
+/// Name of the endpoint set that comes from the `endpoints` setting.
+const CONFIG_SOURCE: &str = "config";
+/// Name of the endpoint set added through the admin API.
+const ADMIN_SOURCE: &str = "admin";
+
+#[derive(Default)]
+struct EndpointSets {
+    /// Endpoint configs by the source providing them.
+    sources: BTreeMap<String, Vec<config::EndpointConfig>>,
+    /// The config each current endpoint was built from, by URL.
+    built: HashMap<String, config::EndpointConfig>,
+    /// Endpoints removed through the admin API, hidden from every source
+    /// until added back.
+    removed: HashSet<String>,
+    /// Limits changed through the admin API, by URL. They apply over any
+    /// source until restart.
+    updates: HashMap<String, EndpointUpdate>,
+}
+
+fn same_url(a: &str, b: &str) -> bool {
+    url_key(a) == url_key(b)
+}
+
+fn url_key(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
 
 pub struct LoadBalancer {
-    pub endpoints: Arc<Vec<Endpoint>>,
+    /// Current endpoint list, replaced wholesale on reload.
+    endpoints: watch::Sender<Arc<Vec<Endpoint>>>,
+    endpoint_sets: Mutex<EndpointSets>,
+    /// Settings new endpoints are built with.
+    config: Config,
     strategy: Box<dyn LoadBalancingStrategy + Send + Sync>,
     health_checker: Arc<HealthChecker>,
+    health_task: Mutex<Option<JoinHandle<()>>>,
+    canary: Mutex<Option<(Arc<canary::Canary>, JoinHandle<()>)>>,
+    events: broadcast::Sender<events::Event>,
     metrics: Arc<Metrics>,
+    priority: PriorityConfig,
+    routing: RoutingConfig,
+    retry: config::RetryConfig,
+    retry_budget: RetryBudget,
+    outlier_detection: OutlierDetectionConfig,
+    stream_failover: failover::StreamFailoverConfig,
+    timeouts: timeouts::TimeoutConfig,
 }
 
 impl LoadBalancer {
@@ -30,79 +149,465 @@ impl LoadBalancer {
         strategy: Box<dyn LoadBalancingStrategy + Send + Sync>,
         health_checker: HealthChecker,
     ) -> Self {
-        let endpoints: Vec<Endpoint> = config
-            .endpoints
+        let events = broadcast::channel(config.events.stream_buffer.max(1)).0;
+        let static_endpoints = config.static_endpoints();
+        let endpoints: Vec<Endpoint> = static_endpoints
             .iter()
-            .map(|ec| Endpoint::new(ec.url.clone(), ec.weight, ec.max_connections))
+            .map(|ec| build_endpoint(&config, ec, &events))
             .collect();
+        let endpoint_sets = EndpointSets {
+            built: static_endpoints
+                .iter()
+                .map(|ec| (ec.url.clone(), ec.clone()))
+                .collect(),
+            sources: BTreeMap::from([(CONFIG_SOURCE.to_string(), static_endpoints)]),
+            ..Default::default()
+        };
 
-        let endpoints = Arc::new(endpoints);
+        let endpoints = watch::channel(Arc::new(endpoints)).0;
 
-        // Clone endpoints for health checker
-        let health_endpoints = endpoints.clone();
+        // The health checker follows the endpoint list as it changes
+        let health_endpoints = endpoints.subscribe();
         let health_checker = Arc::new(health_checker);
         let health_checker_clone = health_checker.clone();
 
         // Spawn health check task
-        tokio::spawn(async move {
+        let health_task = tokio::spawn(async move {
             health_checker_clone
-                .start_health_checks((*health_endpoints).clone())
+                .start_health_checks(health_endpoints)
                 .await;
         });
 
         Self {
             endpoints,
+            endpoint_sets: Mutex::new(endpoint_sets),
+            config: config.clone(),
             strategy,
             health_checker,
+            health_task: Mutex::new(Some(health_task)),
+            canary: Mutex::new(None),
+            events,
             metrics: Arc::new(Metrics::new()),
+            retry_budget: RetryBudget::new(&config.retry),
+            retry: config.retry,
+            priority: config.priority,
+            routing: config.routing,
+            outlier_detection: config.outlier_detection,
+            stream_failover: config.stream_failover,
+            timeouts: config.timeouts,
         }
     }
 
-    pub async fn get_endpoint(&self) -> Result<&Endpoint> {
-        self.metrics.increment_requests();
+    /// Snapshot of the current endpoints.
+    pub fn endpoints(&self) -> Arc<Vec<Endpoint>> {
+        self.endpoints.borrow().clone()
+    }
 
-        // Update metrics for healthy endpoints
-        let healthy_count = self.endpoints.iter().filter(|e| e.is_healthy()).count();
-        self.metrics.set_healthy_endpoints(healthy_count as u64);
+    /// Swaps in the static endpoints of a new configuration, leaving
+    /// discovered ones alone.
+    pub fn reload_endpoints(&self, config: &Config) -> EndpointChanges {
+        self.set_endpoints(CONFIG_SOURCE, config.static_endpoints())
+    }
 
-        // Get the next endpoint using the strategy
-        let endpoint = self.strategy.next_endpoint(&self.endpoints).await?;
+    /// Replaces the endpoints provided by `source`, the config file or a
+    /// discovery provider. Endpoints are matched by URL: unchanged ones are
+    /// kept as they are, changed ones keep their connection counts, health,
+    /// circuit and history, and removed ones finish their in-flight requests
+    /// but get no new ones. A URL listed by several sources is taken from the
+    /// config file first, then from the sources in name order.
+    pub fn set_endpoints(
+        &self,
+        source: &str,
+        endpoints: Vec<config::EndpointConfig>,
+    ) -> EndpointChanges {
+        let mut sets = self.endpoint_sets.lock().unwrap();
+        sets.sources.insert(source.to_string(), endpoints);
+        self.apply_endpoint_sets(&mut sets)
+    }
 
-        // Update active connections metric
-        self.metrics.set_active_connections(
-            self.endpoints
+    /// Adds an endpoint at runtime, alongside those of the config file and
+    /// discovery. It lasts until removed or the server restarts.
+    pub fn add_endpoint(&self, endpoint: config::EndpointConfig) -> Result<EndpointChanges> {
+        if let Some(problem) = validation::endpoint_url_problem(&endpoint.url)
+            .or_else(|| validation::label_problem(&endpoint.labels))
+        {
+            return Err(LoadBalancerError::BadRequest(problem));
+        }
+        if endpoint.weight == 0 {
+            return Err(LoadBalancerError::BadRequest(
+                "weight must be at least 1".to_string(),
+            ));
+        }
+
+        let mut sets = self.endpoint_sets.lock().unwrap();
+        if self.find_endpoint(&endpoint.url).is_ok() {
+            return Err(LoadBalancerError::EndpointExists(
+                url_key(&endpoint.url).to_string(),
+            ));
+        }
+        sets.removed.remove(url_key(&endpoint.url));
+        sets.sources
+            .entry(ADMIN_SOURCE.to_string())
+            .or_default()
+            .push(endpoint);
+        Ok(self.apply_endpoint_sets(&mut sets))
+    }
+
+    /// Removes an endpoint whichever source provides it. In-flight requests
+    /// finish; the endpoint stays out until added back or the server restarts.
+    pub fn remove_endpoint(&self, url: &str) -> Result<EndpointChanges> {
+        let mut sets = self.endpoint_sets.lock().unwrap();
+        let url = self.find_endpoint(url)?.url;
+        if let Some(added) = sets.sources.get_mut(ADMIN_SOURCE) {
+            added.retain(|ec| !same_url(&ec.url, &url));
+        }
+        sets.removed.insert(url_key(&url).to_string());
+        sets.updates.remove(url_key(&url));
+        Ok(self.apply_endpoint_sets(&mut sets))
+    }
+
+    /// Changes an endpoint's weight or connection limit, keeping its state.
+    pub fn update_endpoint(&self, url: &str, update: EndpointUpdate) -> Result<EndpointChanges> {
+        if update.weight == Some(0) {
+            return Err(LoadBalancerError::BadRequest(
+                "weight must be at least 1".to_string(),
+            ));
+        }
+
+        let mut sets = self.endpoint_sets.lock().unwrap();
+        let url = self.find_endpoint(url)?.url;
+        let current = sets.updates.entry(url_key(&url).to_string()).or_default();
+        current.weight = update.weight.or(current.weight);
+        current.max_connections = update.max_connections.or(current.max_connections);
+        Ok(self.apply_endpoint_sets(&mut sets))
+    }
+
+    /// Rebuilds the endpoint list from every source. Callers hold the lock on
+    /// `sets` throughout, so concurrent changes apply one at a time.
+    fn apply_endpoint_sets(&self, sets: &mut EndpointSets) -> EndpointChanges {
+        let current = self.endpoints();
+
+        let mut wanted: Vec<config::EndpointConfig> = Vec::new();
+        let sources = sets.sources.get(CONFIG_SOURCE).into_iter().chain(
+            sets.sources
                 .iter()
-                .map(|e| e.get_connections() as u64)
-                .sum(),
+                .filter(|(name, _)| name.as_str() != CONFIG_SOURCE)
+                .map(|(_, endpoints)| endpoints),
         );
+        for ec in sources.flatten() {
+            if sets.removed.contains(url_key(&ec.url))
+                || wanted.iter().any(|w| same_url(&w.url, &ec.url))
+            {
+                continue;
+            }
+            let mut ec = ec.clone();
+            if let Some(update) = sets.updates.get(url_key(&ec.url)) {
+                ec.weight = update.weight.unwrap_or(ec.weight);
+                ec.max_connections = update.max_connections.unwrap_or(ec.max_connections);
+            }
+            wanted.push(ec);
+        }
 
-        // Check if we should trigger a health check
-        if !endpoint.is_healthy() {
-            warn!(
-                "Endpoint {} is marked as unhealthy, triggering health check",
-                endpoint.url
-            );
-            let health_checker = self.health_checker.clone();
-            let endpoint_clone = endpoint.clone();
-
-            tokio::spawn(async move {
-                match health_checker.check_single_endpoint(&endpoint_clone).await {
-                    Ok(is_healthy) => {
-                        if is_healthy {
-                            info!("Endpoint {} is now healthy", endpoint_clone.url);
-                            endpoint_clone.mark_healthy();
-                        }
+        let mut changes = EndpointChanges::default();
+        let endpoints: Vec<Endpoint> = wanted
+            .iter()
+            .map(|ec| {
+                let previous = current.iter().find(|e| e.url == ec.url);
+                match (previous, sets.built.get(&ec.url)) {
+                    (Some(previous), Some(old)) if old == ec => previous.clone(),
+                    (Some(previous), _) => {
+                        changes.updated.push(ec.url.clone());
+                        build_endpoint(&self.config, ec, &self.events).with_state_of(previous)
                     }
-                    Err(e) => {
-                        warn!("Health check failed for {}: {}", endpoint_clone.url, e);
+                    (None, _) => {
+                        changes.added.push(ec.url.clone());
+                        build_endpoint(&self.config, ec, &self.events)
                     }
                 }
-            });
+            })
+            .collect();
+        changes.removed = current
+            .iter()
+            .filter(|e| !endpoints.iter().any(|n| n.url == e.url))
+            .map(|e| e.url.clone())
+            .collect();
+
+        sets.built = wanted.into_iter().map(|ec| (ec.url.clone(), ec)).collect();
+        self.endpoints.send_replace(Arc::new(endpoints));
+        self.strategy.endpoints_removed(&changes.removed);
+        changes
+    }
+
+    /// Probes an endpoint right away, outside its schedule, and records the
+    /// result like a scheduled check.
+    pub async fn check_endpoint(&self, url: &str) -> Result<bool> {
+        let endpoint = self.find_endpoint(url)?;
+        self.health_checker.check_single_endpoint(&endpoint).await
+    }
+
+    /// Runs canary checks against the endpoints in the background until shutdown.
+    pub fn start_canary(&self, canary: canary::Canary) {
+        let canary = Arc::new(canary);
+        let runner = canary.clone();
+        let endpoints = self.endpoints.subscribe();
+        let task = tokio::spawn(async move { runner.run(endpoints).await });
+        *self.canary.lock().unwrap() = Some((canary, task));
+    }
+
+    /// Stops the background health and canary checks, waiting for any round in
+    /// progress to finish.
+    pub async fn shutdown(&self) {
+        self.health_checker.stop();
+        let task = self.health_task.lock().unwrap().take();
+        if let Some(task) = task {
+            if let Err(e) = task.await {
+                warn!("Health check task ended abnormally: {}", e);
+            }
+        }
+
+        let canary = self.canary.lock().unwrap().take();
+        if let Some((canary, task)) = canary {
+            canary.stop();
+            if let Err(e) = task.await {
+                warn!("Canary task ended abnormally: {}", e);
+            }
         }
+    }
+
+    pub fn find_endpoint(&self, url: &str) -> Result<Endpoint> {
+        self.endpoints()
+            .iter()
+            .find(|e| same_url(&e.url, url))
+            .cloned()
+            .ok_or_else(|| {
+                LoadBalancerError::EndpointNotFound(url.trim_end_matches('/').to_string())
+            })
+    }
+
+    /// Requests currently being proxied, summed over all endpoints.
+    pub fn in_flight(&self) -> u64 {
+        self.endpoints()
+            .iter()
+            .map(|e| e.get_connections() as u64)
+            .sum()
+    }
+
+    /// Fraction of available capacity in use: active connections over the summed
+    /// `max_connections` of available endpoints. 1.0 when nothing is available.
+    pub fn saturation(&self) -> f64 {
+        let (active, capacity) = self.endpoints().iter().filter(|e| e.is_available()).fold(
+            (0u64, 0u64),
+            |(active, capacity), e| {
+                (
+                    active + e.get_connections() as u64,
+                    capacity + e.max_connections as u64,
+                )
+            },
+        );
+
+        if capacity == 0 {
+            1.0
+        } else {
+            active as f64 / capacity as f64
+        }
+    }
+
+    /// Feeds the result of a proxied request back into the endpoint's circuit breaker
+    /// and outlier state, ejecting it when it crosses one of the configured thresholds.
+    pub fn record_outcome(&self, endpoint: &Endpoint, outcome: Outcome) {
+        endpoint.record_circuit_result(outcome == Outcome::Success);
+
+        let config = &self.outlier_detection;
+        if !config.enabled {
+            return;
+        }
+
+        let reason = match endpoint.record_outlier_outcome(outcome, config) {
+            Some(reason) => reason,
+            None => return,
+        };
+
+        let endpoints = self.endpoints();
+        let ejected = endpoints.iter().filter(|e| e.is_ejected()).count();
+        if ejected >= outlier::max_ejected(endpoints.len(), config) {
+            warn!(
+                "Endpoint {} is an outlier ({}) but {} of {} endpoints are already ejected",
+                endpoint.url,
+                reason.as_str(),
+                ejected,
+                endpoints.len()
+            );
+            return;
+        }
+
+        let duration = endpoint.eject(config);
+        warn!(
+            "Ejecting outlier endpoint {} for {}s ({})",
+            endpoint.url,
+            duration.as_secs(),
+            reason.as_str()
+        );
+        self.metrics
+            .increment_ejections(&endpoint.url, reason.as_str());
+        endpoint.record_event(EventKind::Ejected {
+            reason: reason.as_str().to_string(),
+            seconds: duration.as_secs(),
+        });
+        self.metrics.set_ejected_endpoints(ejected as u64 + 1);
+    }
+
+    pub fn priority_config(&self) -> &PriorityConfig {
+        &self.priority
+    }
+
+    pub fn routing_config(&self) -> &RoutingConfig {
+        &self.routing
+    }
+
+    pub fn retry_config(&self) -> &config::RetryConfig {
+        &self.retry
+    }
+
+    pub fn stream_failover_config(&self) -> &failover::StreamFailoverConfig {
+        &self.stream_failover
+    }
+
+    pub fn timeout_config(&self) -> &timeouts::TimeoutConfig {
+        &self.timeouts
+    }
+
+    pub fn retry_budget(&self) -> &RetryBudget {
+        &self.retry_budget
+    }
+
+    /// Admission control for a request of the given class. Requests are held back
+    /// while saturation exceeds the class threshold and shed once their queue time runs out.
+    /// Fails straight away when no endpoint is available, as waiting would not help.
+    pub async fn admit(&self, priority: Priority) -> Result<()> {
+        let policy = self.priority.policy(priority);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(policy.max_queue_ms);
+        let mut delayed = false;
+
+        loop {
+            if !self.endpoints().iter().any(|e| e.is_available()) {
+                return Err(LoadBalancerError::NoHealthyEndpoints);
+            }
+            let saturation = self.saturation();
+            self.metrics.set_saturation(saturation);
+
+            if saturation <= policy.shed_threshold {
+                self.metrics.increment_admitted(priority);
+                return Ok(());
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                warn!(
+                    "Shedding {} priority request at saturation {:.2}",
+                    priority, saturation
+                );
+                self.metrics.increment_shed(priority);
+                return Err(LoadBalancerError::LoadShed(priority));
+            }
+
+            if !delayed {
+                delayed = true;
+                self.metrics.increment_delayed(priority);
+            }
+            tokio::time::sleep(ADMISSION_POLL_INTERVAL).await;
+        }
+    }
+
+    pub async fn get_endpoint(&self, model: Option<&str>, route: &Route) -> Result<Endpoint> {
+        self.metrics.increment_requests();
+        self.select_endpoint(model, &[], route).await
+    }
+
+    /// Picks an endpoint for the model with the configured strategy, skipping the
+    /// given URLs and endpoints failing their canary for the model. Used by
+    /// retries to move a failed request onto a different endpoint. The route
+    /// narrows the endpoints down before the strategy sees them.
+    pub async fn select_endpoint(
+        &self,
+        model: Option<&str>,
+        exclude: &[String],
+        route: &Route,
+    ) -> Result<Endpoint> {
+        let endpoints = self.endpoints();
+
+        // Update metrics for healthy endpoints
+        let healthy_count = endpoints.iter().filter(|e| e.is_healthy()).count();
+        self.metrics.set_healthy_endpoints(healthy_count as u64);
+        let ejected_count = endpoints.iter().filter(|e| e.is_ejected()).count();
+        self.metrics.set_ejected_endpoints(ejected_count as u64);
+        self.metrics.set_saturation(self.saturation());
+
+        let ranked;
+        let groups: &[Vec<Endpoint>] = if route.is_empty() {
+            std::slice::from_ref(endpoints.as_ref())
+        } else {
+            ranked = route.rank(&endpoints)?;
+            &ranked
+        };
+
+        // Get the next endpoint using the strategy, moving on if its circuit
+        // breaker has no trial slot left by the time it is picked
+        let mut skipped = exclude.to_vec();
+        if let Some(model) = model {
+            skipped.extend(
+                endpoints
+                    .iter()
+                    .filter(|e| e.is_model_failing(model))
+                    .map(|e| e.url.clone()),
+            );
+        }
+        let endpoint = loop {
+            let endpoint = self.next_endpoint(groups, &skipped).await?;
+            if endpoint.try_acquire_circuit() {
+                break endpoint;
+            }
+            skipped.push(endpoint.url.clone());
+        };
+
+        // Update active connections metric
+        self.metrics.set_active_connections(self.in_flight());
 
         Ok(endpoint)
     }
 
+    /// Asks the strategy for an endpoint from the first group that has one
+    /// available with a free connection, leaving out the skipped URLs.
+    async fn next_endpoint(
+        &self,
+        groups: &[Vec<Endpoint>],
+        skipped: &[String],
+    ) -> Result<Endpoint> {
+        let mut at_capacity = false;
+        for group in groups {
+            let mut candidates = Vec::with_capacity(group.len());
+            for endpoint in group.iter().filter(|e| !skipped.contains(&e.url)) {
+                if endpoint.has_capacity() {
+                    candidates.push(endpoint.clone());
+                } else if endpoint.is_available() {
+                    at_capacity = true;
+                }
+            }
+            match self.strategy.next_endpoint(&candidates).await.cloned() {
+                Ok(endpoint) => return Ok(endpoint),
+                Err(LoadBalancerError::NoHealthyEndpoints) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        if at_capacity {
+            return Err(LoadBalancerError::AtCapacity);
+        }
+        Err(LoadBalancerError::NoHealthyEndpoints)
+    }
+
+    /// Live fleet events from every endpoint.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<events::Event> {
+        self.events.subscribe()
+    }
+
     pub fn get_metrics(&self) -> Arc<Metrics> {
         self.metrics.clone()
     }
@@ -1,9 +1,17 @@
+use crate::circuit_breaker::CircuitState;
+use crate::health_state::HealthState;
+use crate::priority::Priority;
 use metrics::{register_counter, register_gauge, Counter, Gauge};
 
 pub struct Metrics {
     requests_total: Counter,
     active_connections: Gauge,
     healthy_endpoints: Gauge,
+    saturation: Gauge,
+    retries_total: Counter,
+    retry_budget_exhausted: Counter,
+    ejected_endpoints: Gauge,
+    stream_failovers: Counter,
 }
 
 impl Metrics {
@@ -12,6 +20,11 @@ impl Metrics {
             requests_total: register_counter!("lb_requests_total"),
             active_connections: register_gauge!("lb_active_connections"),
             healthy_endpoints: register_gauge!("lb_healthy_endpoints"),
+            saturation: register_gauge!("lb_saturation"),
+            retries_total: register_counter!("lb_retries_total"),
+            retry_budget_exhausted: register_counter!("lb_retry_budget_exhausted_total"),
+            ejected_endpoints: register_gauge!("lb_ejected_endpoints"),
+            stream_failovers: register_counter!("lb_stream_failovers_total"),
         }
     }
 
@@ -26,4 +39,82 @@ impl Metrics {
     pub fn set_healthy_endpoints(&self, count: u64) {
         self.healthy_endpoints.set(count as f64);
     }
+
+    pub fn set_saturation(&self, saturation: f64) {
+        self.saturation.set(saturation);
+    }
+
+    pub fn increment_retries(&self) {
+        self.retries_total.increment(1);
+    }
+
+    pub fn increment_retry_budget_exhausted(&self) {
+        self.retry_budget_exhausted.increment(1);
+    }
+
+    pub fn increment_stream_failovers(&self) {
+        self.stream_failovers.increment(1);
+    }
+
+    pub fn set_ejected_endpoints(&self, count: u64) {
+        self.ejected_endpoints.set(count as f64);
+    }
+
+    pub fn increment_ejections(&self, endpoint: &str, reason: &'static str) {
+        register_counter!(
+            "lb_outlier_ejections_total",
+            "endpoint" => endpoint.to_string(),
+            "reason" => reason
+        )
+        .increment(1);
+    }
+
+    pub fn increment_cancelled(&self, stage: &'static str) {
+        register_counter!("lb_cancelled_requests_total", "stage" => stage).increment(1);
+    }
+
+    pub fn increment_admitted(&self, priority: Priority) {
+        register_counter!("lb_priority_admitted_total", "class" => priority.as_str()).increment(1);
+    }
+
+    pub fn increment_delayed(&self, priority: Priority) {
+        register_counter!("lb_priority_delayed_total", "class" => priority.as_str()).increment(1);
+    }
+
+    pub fn increment_shed(&self, priority: Priority) {
+        register_counter!("lb_priority_shed_total", "class" => priority.as_str()).increment(1);
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn record_circuit_transition(endpoint: &str, state: CircuitState) {
+    register_counter!(
+        "lb_circuit_transitions_total",
+        "endpoint" => endpoint.to_string(),
+        "state" => state.as_str()
+    )
+    .increment(1);
+}
+
+pub fn record_health_transition(endpoint: &str, state: HealthState) {
+    register_counter!(
+        "lb_health_transitions_total",
+        "endpoint" => endpoint.to_string(),
+        "state" => state.as_str()
+    )
+    .increment(1);
+}
+
+pub fn record_canary_failure(endpoint: &str, model: &str) {
+    register_counter!(
+        "lb_canary_failures_total",
+        "endpoint" => endpoint.to_string(),
+        "model" => model.to_string()
+    )
+    .increment(1);
 }
@@ -1,5 +1,7 @@
+use crate::events::EventKind;
 use crate::{Endpoint, LoadBalancerError};
-use serde::Deserialize;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
 use std::result::Result as StdResult;
 use tracing::info;
 
@@ -32,6 +34,12 @@ pub struct ModelManager {
     client: reqwest::Client,
 }
 
+impl Default for ModelManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ModelManager {
     pub fn new() -> Self {
         Self {
@@ -43,7 +51,6 @@ impl ModelManager {
         match self.is_model_present(endpoint, model_name).await {
             Ok(true) => {
                 info!("Model {} already present on {}", model_name, endpoint.url);
-                endpoint.mark_healthy();
                 Ok(())
             }
             Ok(false) => {
@@ -57,50 +64,62 @@ impl ModelManager {
                             "Successfully installed model {} on {}",
                             model_name, endpoint.url
                         );
-                        endpoint.mark_healthy();
+                        endpoint.record_event(EventKind::ModelPulled {
+                            model: model_name.to_string(),
+                        });
                         Ok(())
                     }
-                    Err(e) => {
-                        endpoint.mark_unhealthy();
-                        Err(e)
-                    }
+                    Err(e) => Err(e),
                 }
             }
-            Err(e) => {
-                endpoint.mark_unhealthy();
-                Err(e)
-            }
+            Err(e) => Err(e),
         }
     }
 
     pub async fn is_model_present(&self, endpoint: &Endpoint, model_name: &str) -> Result<bool> {
+        let models = self.fetch_models(endpoint).await?;
+
+        // Check if any model matches the required model name
+        Ok(models
+            .iter()
+            .any(|model| model.name == model_name || model.model == model_name))
+    }
+
+    /// Names of the models installed on an endpoint.
+    pub async fn list_models(&self, endpoint: &Endpoint) -> Result<Vec<String>> {
+        let models = self.fetch_models(endpoint).await?;
+        Ok(models.into_iter().map(|model| model.name).collect())
+    }
+
+    async fn fetch_models(&self, endpoint: &Endpoint) -> Result<Vec<Model>> {
         let url = format!("{}/api/tags", endpoint.url);
         let response = self
             .client
             .get(&url)
             .send()
             .await
-            .map_err(|e| LoadBalancerError::HttpError(e))?;
+            .map_err(LoadBalancerError::HttpError)?;
 
         let models: ModelsResponse = response
             .json()
             .await
-            .map_err(|e| LoadBalancerError::HttpError(e))?;
-
-        // Check if any model matches the required model name
-        Ok(models.models.iter().any(|model| {
-            let model_matches = model.name == model_name || model.model == model_name;
-            if model_matches {
-                // info!(
-                //     "Found model {} on {} (size: {}B, modified: {})",
-                //     model.name, endpoint.url, model.size, model.modified_at
-                // );
-            }
-            model_matches
-        }))
+            .map_err(LoadBalancerError::HttpError)?;
+        Ok(models.models)
     }
 
     async fn pull_model(&self, endpoint: &Endpoint, model_name: &str) -> Result<()> {
+        self.pull_model_with_progress(endpoint, model_name, |_| {})
+            .await
+    }
+
+    /// Pulls a model, reporting each progress update Ollama streams back, and
+    /// returns once the pull has finished.
+    pub async fn pull_model_with_progress(
+        &self,
+        endpoint: &Endpoint,
+        model_name: &str,
+        mut on_progress: impl FnMut(PullProgress) + Send,
+    ) -> Result<()> {
         let url = format!("{}/api/pull", endpoint.url);
         let body = serde_json::json!({
             "name": model_name
@@ -114,7 +133,7 @@ impl ModelManager {
             .json(&body)
             .send()
             .await
-            .map_err(|e| LoadBalancerError::HttpError(e))?;
+            .map_err(LoadBalancerError::HttpError)?;
 
         if !response.status().is_success() {
             let error_text = response
@@ -127,6 +146,24 @@ impl ModelManager {
             )));
         }
 
+        // One JSON object per line, ending with a "success" status or an error
+        let mut stream = response.bytes_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        loop {
+            let chunk = stream.next().await.transpose()?;
+            if let Some(chunk) = &chunk {
+                buffer.extend_from_slice(chunk);
+            }
+            while let Some(newline) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=newline).collect();
+                report_progress(&line, &mut on_progress)?;
+            }
+            if chunk.is_none() {
+                report_progress(&buffer, &mut on_progress)?;
+                break;
+            }
+        }
+
         info!(
             "Successfully pulled model {} on {}",
             model_name, endpoint.url
@@ -134,3 +171,40 @@ impl ModelManager {
         Ok(())
     }
 }
+
+/// One progress update of a model pull.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PullLine {
+    #[serde(flatten)]
+    progress: PullProgress,
+    error: Option<String>,
+}
+
+fn report_progress(line: &[u8], on_progress: &mut impl FnMut(PullProgress)) -> Result<()> {
+    if line.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(());
+    }
+    let line: PullLine = serde_json::from_slice(line).map_err(|e| {
+        LoadBalancerError::ConfigError(format!("Unexpected pull progress from Ollama: {}", e))
+    })?;
+    if let Some(error) = line.error {
+        return Err(LoadBalancerError::ConfigError(format!(
+            "Failed to pull model: {}",
+            error
+        )));
+    }
+    on_progress(line.progress);
+    Ok(())
+}
@@ -0,0 +1,324 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Result of a proxied request, as seen by outlier detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Error,
+    Timeout,
+}
+
+/// Why an endpoint was ejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EjectionReason {
+    ConsecutiveErrors,
+    ConsecutiveTimeouts,
+    ErrorRate,
+}
+
+impl EjectionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EjectionReason::ConsecutiveErrors => "consecutive_errors",
+            EjectionReason::ConsecutiveTimeouts => "consecutive_timeouts",
+            EjectionReason::ErrorRate => "error_rate",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutlierDetectionConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_consecutive_errors")]
+    pub consecutive_errors: u32,
+    #[serde(default = "default_consecutive_timeouts")]
+    pub consecutive_timeouts: u32,
+    /// Error fraction over `window_seconds` that ejects an endpoint.
+    #[serde(default = "default_error_rate_threshold")]
+    pub error_rate_threshold: f64,
+    #[serde(default = "default_window_seconds")]
+    pub window_seconds: u64,
+    /// Requests needed in the window before the error rate is trusted.
+    #[serde(default = "default_min_requests")]
+    pub min_requests: u32,
+    /// First ejection length; each repeat ejection adds another multiple of it.
+    #[serde(default = "default_base_ejection_seconds")]
+    pub base_ejection_seconds: u64,
+    #[serde(default = "default_max_ejection_seconds")]
+    pub max_ejection_seconds: u64,
+    /// Upper bound on the share of endpoints that may be ejected at once. At
+    /// least one endpoint may always be ejected unless this is 0.
+    #[serde(default = "default_max_ejection_percent")]
+    pub max_ejection_percent: u32,
+}
+
+impl Default for OutlierDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            consecutive_errors: default_consecutive_errors(),
+            consecutive_timeouts: default_consecutive_timeouts(),
+            error_rate_threshold: default_error_rate_threshold(),
+            window_seconds: default_window_seconds(),
+            min_requests: default_min_requests(),
+            base_ejection_seconds: default_base_ejection_seconds(),
+            max_ejection_seconds: default_max_ejection_seconds(),
+            max_ejection_percent: default_max_ejection_percent(),
+        }
+    }
+}
+
+/// Per-endpoint traffic statistics used to spot outliers.
+#[derive(Debug, Default)]
+pub struct OutlierState {
+    consecutive_errors: u32,
+    consecutive_timeouts: u32,
+    window: VecDeque<(Instant, bool)>,
+    ejection_count: u32,
+    ejected_until: Option<Instant>,
+}
+
+impl OutlierState {
+    pub fn is_ejected(&self) -> bool {
+        self.ejected_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    pub fn ejected_until(&self) -> Option<Instant> {
+        self.ejected_until.filter(|until| Instant::now() < *until)
+    }
+
+    /// Records an outcome and returns the reason if it pushes the endpoint over a threshold.
+    pub fn record(
+        &mut self,
+        outcome: Outcome,
+        config: &OutlierDetectionConfig,
+    ) -> Option<EjectionReason> {
+        let now = Instant::now();
+        let window = Duration::from_secs(config.window_seconds);
+        while self
+            .window
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > window)
+        {
+            self.window.pop_front();
+        }
+        self.window.push_back((now, outcome == Outcome::Success));
+
+        match outcome {
+            Outcome::Success => {
+                self.consecutive_errors = 0;
+                self.consecutive_timeouts = 0;
+                return None;
+            }
+            Outcome::Error => {
+                self.consecutive_errors += 1;
+                self.consecutive_timeouts = 0;
+            }
+            Outcome::Timeout => {
+                self.consecutive_errors += 1;
+                self.consecutive_timeouts += 1;
+            }
+        }
+
+        if self.is_ejected() {
+            return None;
+        }
+
+        if config.consecutive_timeouts > 0
+            && self.consecutive_timeouts >= config.consecutive_timeouts
+        {
+            return Some(EjectionReason::ConsecutiveTimeouts);
+        }
+        if config.consecutive_errors > 0 && self.consecutive_errors >= config.consecutive_errors {
+            return Some(EjectionReason::ConsecutiveErrors);
+        }
+        if self.window.len() >= config.min_requests.max(1) as usize {
+            let failures = self.window.iter().filter(|(_, ok)| !ok).count();
+            if failures as f64 / self.window.len() as f64 >= config.error_rate_threshold {
+                return Some(EjectionReason::ErrorRate);
+            }
+        }
+        None
+    }
+
+    /// Ejects the endpoint for `base * n` seconds, where n grows with every ejection
+    /// and starts over once the endpoint has stayed in rotation for `max_ejection_seconds`.
+    pub fn eject(&mut self, config: &OutlierDetectionConfig) -> Duration {
+        let now = Instant::now();
+        let max = Duration::from_secs(config.max_ejection_seconds);
+        if self
+            .ejected_until
+            .is_some_and(|until| now.saturating_duration_since(until) >= max)
+        {
+            self.ejection_count = 0;
+        }
+
+        self.ejection_count += 1;
+        let duration =
+            (Duration::from_secs(config.base_ejection_seconds) * self.ejection_count).min(max);
+        self.ejected_until = Some(now + duration);
+        self.consecutive_errors = 0;
+        self.consecutive_timeouts = 0;
+        self.window.clear();
+        duration
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_consecutive_errors() -> u32 {
+    5
+}
+
+fn default_consecutive_timeouts() -> u32 {
+    3
+}
+
+fn default_error_rate_threshold() -> f64 {
+    0.5
+}
+
+fn default_window_seconds() -> u64 {
+    30
+}
+
+fn default_min_requests() -> u32 {
+    10
+}
+
+fn default_base_ejection_seconds() -> u64 {
+    30
+}
+
+fn default_max_ejection_seconds() -> u64 {
+    300
+}
+
+fn default_max_ejection_percent() -> u32 {
+    50
+}
+
+/// How many of `endpoints` may be ejected at once.
+pub fn max_ejected(endpoints: usize, config: &OutlierDetectionConfig) -> usize {
+    if config.max_ejection_percent == 0 {
+        return 0;
+    }
+    (endpoints * config.max_ejection_percent as usize / 100).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> OutlierDetectionConfig {
+        OutlierDetectionConfig {
+            consecutive_errors: 3,
+            consecutive_timeouts: 2,
+            error_rate_threshold: 0.5,
+            min_requests: 4,
+            base_ejection_seconds: 10,
+            max_ejection_seconds: 25,
+            ..OutlierDetectionConfig::default()
+        }
+    }
+
+    #[test]
+    fn consecutive_failures_are_reported() {
+        let config = config();
+        let mut state = OutlierState::default();
+        assert_eq!(state.record(Outcome::Error, &config), None);
+        assert_eq!(state.record(Outcome::Error, &config), None);
+        assert_eq!(
+            state.record(Outcome::Error, &config),
+            Some(EjectionReason::ConsecutiveErrors)
+        );
+
+        let mut state = OutlierState::default();
+        assert_eq!(state.record(Outcome::Timeout, &config), None);
+        assert_eq!(
+            state.record(Outcome::Timeout, &config),
+            Some(EjectionReason::ConsecutiveTimeouts)
+        );
+    }
+
+    #[test]
+    fn a_success_resets_the_consecutive_counts() {
+        let config = OutlierDetectionConfig {
+            min_requests: 100,
+            ..config()
+        };
+        let mut state = OutlierState::default();
+        for _ in 0..5 {
+            assert_eq!(state.record(Outcome::Error, &config), None);
+            assert_eq!(state.record(Outcome::Error, &config), None);
+            assert_eq!(state.record(Outcome::Success, &config), None);
+        }
+    }
+
+    #[test]
+    fn the_error_rate_needs_min_requests() {
+        let config = OutlierDetectionConfig {
+            consecutive_errors: 0,
+            ..config()
+        };
+        let mut state = OutlierState::default();
+        assert_eq!(state.record(Outcome::Success, &config), None);
+        assert_eq!(state.record(Outcome::Error, &config), None);
+        assert_eq!(state.record(Outcome::Success, &config), None);
+        assert_eq!(
+            state.record(Outcome::Error, &config),
+            Some(EjectionReason::ErrorRate)
+        );
+    }
+
+    #[test]
+    fn repeat_ejections_last_longer_up_to_the_max() {
+        let config = config();
+        let mut state = OutlierState::default();
+        assert_eq!(state.eject(&config), Duration::from_secs(10));
+        assert!(state.is_ejected());
+        assert_eq!(state.eject(&config), Duration::from_secs(20));
+        assert_eq!(state.eject(&config), Duration::from_secs(25));
+    }
+
+    #[test]
+    fn the_ejection_duration_resets_after_max_ejection_seconds_in_rotation() {
+        let config = config();
+        let back_in_rotation_for = |seconds| {
+            Some(
+                Instant::now()
+                    .checked_sub(Duration::from_secs(seconds))
+                    .unwrap(),
+            )
+        };
+        let mut state = OutlierState::default();
+        assert_eq!(state.eject(&config), Duration::from_secs(10));
+
+        // A shorter quiet period keeps the escalation going
+        state.ejected_until = back_in_rotation_for(24);
+        assert_eq!(state.eject(&config), Duration::from_secs(20));
+
+        state.ejected_until = back_in_rotation_for(25);
+        assert_eq!(state.eject(&config), Duration::from_secs(10));
+        assert_eq!(state.eject(&config), Duration::from_secs(20));
+    }
+
+    #[test]
+    fn at_least_one_endpoint_may_be_ejected() {
+        let config = config();
+        assert_eq!(max_ejected(1, &config), 1);
+        assert_eq!(max_ejected(2, &config), 1);
+        assert_eq!(max_ejected(10, &config), 5);
+        let config = OutlierDetectionConfig {
+            max_ejection_percent: 0,
+            ..config
+        };
+        assert_eq!(max_ejected(10, &config), 0);
+    }
+}
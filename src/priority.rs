@@ -0,0 +1,211 @@
+use http::HeaderMap;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Interactive,
+    Normal,
+    Batch,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Normal => "normal",
+            Priority::Batch => "batch",
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Priority {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "interactive" => Ok(Priority::Interactive),
+            "normal" => Ok(Priority::Normal),
+            "batch" => Ok(Priority::Batch),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Admission policy for a single priority class.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClassPolicy {
+    /// Saturation (0.0 - 1.0) above which requests of this class are held back.
+    pub shed_threshold: f64,
+    /// How long a held-back request may wait for saturation to drop before it is shed.
+    #[serde(default)]
+    pub max_queue_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClassPolicies {
+    #[serde(default = "default_interactive_policy")]
+    pub interactive: ClassPolicy,
+    #[serde(default = "default_normal_policy")]
+    pub normal: ClassPolicy,
+    #[serde(default = "default_batch_policy")]
+    pub batch: ClassPolicy,
+}
+
+impl Default for ClassPolicies {
+    fn default() -> Self {
+        Self {
+            interactive: default_interactive_policy(),
+            normal: default_normal_policy(),
+            batch: default_batch_policy(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PriorityConfig {
+    #[serde(default = "default_priority_header")]
+    pub header: String,
+    #[serde(default = "default_class")]
+    pub default_class: Priority,
+    /// Bearer tokens pinned to a class. A pinned key cannot be escalated through the header.
+    #[serde(default)]
+    pub api_keys: HashMap<String, Priority>,
+    #[serde(default)]
+    pub classes: ClassPolicies,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        Self {
+            header: default_priority_header(),
+            default_class: default_class(),
+            api_keys: HashMap::new(),
+            classes: ClassPolicies::default(),
+        }
+    }
+}
+
+impl PriorityConfig {
+    /// Resolves the priority of a request: key policy first, then the priority header, then the default.
+    pub fn classify(&self, headers: &HeaderMap) -> Priority {
+        let api_key = headers
+            .get(http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+        if let Some(priority) = api_key.and_then(|key| self.api_keys.get(key)) {
+            return *priority;
+        }
+
+        headers
+            .get(self.header.as_str())
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(self.default_class)
+    }
+
+    pub fn policy(&self, priority: Priority) -> &ClassPolicy {
+        match priority {
+            Priority::Interactive => &self.classes.interactive,
+            Priority::Normal => &self.classes.normal,
+            Priority::Batch => &self.classes.batch,
+        }
+    }
+}
+
+fn default_priority_header() -> String {
+    "x-ollama-priority".to_string()
+}
+
+fn default_class() -> Priority {
+    Priority::Normal
+}
+
+fn default_interactive_policy() -> ClassPolicy {
+    ClassPolicy {
+        shed_threshold: 1.0,
+        max_queue_ms: 0,
+    }
+}
+
+fn default_normal_policy() -> ClassPolicy {
+    ClassPolicy {
+        shed_threshold: 0.9,
+        max_queue_ms: 1000,
+    }
+}
+
+fn default_batch_policy() -> ClassPolicy {
+    ClassPolicy {
+        shed_threshold: 0.75,
+        max_queue_ms: 5000,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn config() -> PriorityConfig {
+        PriorityConfig {
+            api_keys: HashMap::from([("batch-key".to_string(), Priority::Batch)]),
+            ..PriorityConfig::default()
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn pinned_keys_cannot_be_escalated() {
+        let priority = config().classify(&headers(&[
+            ("authorization", "Bearer batch-key"),
+            ("x-ollama-priority", "interactive"),
+        ]));
+        assert_eq!(priority, Priority::Batch);
+    }
+
+    #[test]
+    fn the_header_sets_the_class_of_other_requests() {
+        let config = config();
+        let classify = |pairs| config.classify(&headers(pairs));
+        assert_eq!(
+            classify(&[
+                ("authorization", "Bearer other-key"),
+                ("x-ollama-priority", " Interactive "),
+            ]),
+            Priority::Interactive
+        );
+        assert_eq!(classify(&[("x-ollama-priority", "batch")]), Priority::Batch);
+        assert_eq!(
+            classify(&[("x-ollama-priority", "urgent")]),
+            Priority::Normal
+        );
+        assert_eq!(classify(&[]), Priority::Normal);
+    }
+
+    #[test]
+    fn default_policies_shed_batch_first() {
+        let config = PriorityConfig::default();
+        let threshold = |priority| config.policy(priority).shed_threshold;
+        assert!(threshold(Priority::Batch) < threshold(Priority::Normal));
+        assert!(threshold(Priority::Normal) < threshold(Priority::Interactive));
+        assert_eq!(config.policy(Priority::Interactive).max_queue_ms, 0);
+    }
+}
@@ -0,0 +1,506 @@
+use crate::endpoint::Endpoint;
+use crate::error::{LoadBalancerError, Result};
+use crate::health::{HealthCheck, HttpHealthCheck};
+use crate::model_manager::ModelManager;
+use async_trait::async_trait;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Health probe selectable globally under `health_check.probe` or per endpoint.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProbeConfig {
+    /// `GET /` followed by an `/api/tags` lookup of the required model.
+    #[default]
+    Http,
+    /// Plain TCP connect to the endpoint's host and port.
+    Tcp,
+    /// `GET /api/version`, optionally requiring at least `min_version`.
+    Version { min_version: Option<String> },
+    /// `/api/tags` lookup of `model`, the required model by default.
+    Model { model: Option<String> },
+    /// One-token generation, failing if it takes longer than `max_latency_ms`.
+    Generate {
+        model: Option<String>,
+        #[serde(default = "default_generate_prompt")]
+        prompt: String,
+        max_latency_ms: Option<u64>,
+    },
+    /// Passes only if every probe passes.
+    All { probes: Vec<ProbeConfig> },
+    /// Passes if any probe passes.
+    Any { probes: Vec<ProbeConfig> },
+}
+
+fn default_generate_prompt() -> String {
+    "Hi".to_string()
+}
+
+pub type Probe = Arc<dyn HealthCheck + Send + Sync>;
+
+impl ProbeConfig {
+    pub fn build(&self, required_model: &str) -> Probe {
+        let model = |model: &Option<String>| model.clone().unwrap_or(required_model.to_string());
+        match self {
+            ProbeConfig::Http => Arc::new(HttpHealthCheck::new(required_model.to_string())),
+            ProbeConfig::Tcp => Arc::new(TcpProbe),
+            ProbeConfig::Version { min_version } => Arc::new(VersionProbe {
+                client: reqwest::Client::new(),
+                min_version: min_version.clone(),
+            }),
+            ProbeConfig::Model { model: name } => Arc::new(ModelProbe {
+                model_manager: ModelManager::new(),
+                model: model(name),
+            }),
+            ProbeConfig::Generate {
+                model: name,
+                prompt,
+                max_latency_ms,
+            } => Arc::new(GenerateProbe {
+                client: reqwest::Client::new(),
+                model: model(name),
+                prompt: prompt.clone(),
+                max_latency: max_latency_ms.map(Duration::from_millis),
+            }),
+            ProbeConfig::All { probes } => Arc::new(CompositeProbe {
+                require_all: true,
+                probes: probes.iter().map(|p| p.build(required_model)).collect(),
+            }),
+            ProbeConfig::Any { probes } => Arc::new(CompositeProbe {
+                require_all: false,
+                probes: probes.iter().map(|p| p.build(required_model)).collect(),
+            }),
+        }
+    }
+}
+
+fn failed(reason: String) -> LoadBalancerError {
+    LoadBalancerError::HealthCheckError(reason)
+}
+
+/// The failure reason without the `Health check failed:` prefix.
+fn reason(result: Result<bool>) -> Option<String> {
+    match result {
+        Ok(true) => None,
+        Ok(false) => Some("reported unhealthy".to_string()),
+        Err(LoadBalancerError::HealthCheckError(reason)) => Some(reason),
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+pub struct TcpProbe;
+
+#[async_trait]
+impl HealthCheck for TcpProbe {
+    async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
+        let url = reqwest::Url::parse(&endpoint.url)
+            .map_err(|e| failed(format!("invalid endpoint url: {}", e)))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| failed("endpoint url has no host".to_string()))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| failed("endpoint url has no port".to_string()))?;
+
+        tokio::net::TcpStream::connect((host, port))
+            .await
+            .map_err(|e| failed(format!("tcp connect to {}:{} failed: {}", host, port, e)))?;
+        Ok(true)
+    }
+}
+
+pub struct VersionProbe {
+    client: reqwest::Client,
+    min_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+#[async_trait]
+impl HealthCheck for VersionProbe {
+    async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
+        let response = self
+            .client
+            .get(format!("{}/api/version", endpoint.url))
+            .send()
+            .await
+            .map_err(|e| failed(format!("version check failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(failed(format!(
+                "version check returned status {}",
+                response.status()
+            )));
+        }
+        let version: VersionResponse = response
+            .json()
+            .await
+            .map_err(|e| failed(format!("unreadable version response: {}", e)))?;
+
+        match &self.min_version {
+            Some(min) if compare_versions(&version.version, min) == Ordering::Less => Err(failed(
+                format!("version {} is older than {}", version.version, min),
+            )),
+            _ => Ok(true),
+        }
+    }
+}
+
+/// Compares dotted version numbers, ignoring any pre-release suffix.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+pub struct ModelProbe {
+    model_manager: ModelManager,
+    model: String,
+}
+
+#[async_trait]
+impl HealthCheck for ModelProbe {
+    async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
+        match self
+            .model_manager
+            .is_model_present(endpoint, &self.model)
+            .await
+        {
+            Ok(true) => Ok(true),
+            Ok(false) => Err(failed(format!("model {} not found", self.model))),
+            Err(e) => Err(failed(format!(
+                "model lookup for {} failed: {}",
+                self.model, e
+            ))),
+        }
+    }
+}
+
+pub struct GenerateProbe {
+    client: reqwest::Client,
+    model: String,
+    prompt: String,
+    max_latency: Option<Duration>,
+}
+
+#[async_trait]
+impl HealthCheck for GenerateProbe {
+    async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
+        let started = Instant::now();
+        let response = self
+            .client
+            .post(format!("{}/api/generate", endpoint.url))
+            .json(&serde_json::json!({
+                "model": self.model,
+                "prompt": self.prompt,
+                "stream": false,
+                "options": { "num_predict": 1 },
+            }))
+            .send()
+            .await
+            .map_err(|e| failed(format!("generation with {} failed: {}", self.model, e)))?;
+        let status = response.status();
+        // Read the whole answer so the latency covers the generation itself
+        response
+            .bytes()
+            .await
+            .map_err(|e| failed(format!("generation with {} failed: {}", self.model, e)))?;
+        let elapsed = started.elapsed();
+
+        if !status.is_success() {
+            return Err(failed(format!(
+                "generation with {} returned status {}",
+                self.model, status
+            )));
+        }
+        match self.max_latency {
+            Some(limit) if elapsed > limit => Err(failed(format!(
+                "generation with {} took {}ms, over the {}ms limit",
+                self.model,
+                elapsed.as_millis(),
+                limit.as_millis()
+            ))),
+            _ => Ok(true),
+        }
+    }
+}
+
+/// Runs its probes concurrently and combines them with AND or OR, settling
+/// as soon as one failure (AND) or one success (OR) decides the outcome.
+pub struct CompositeProbe {
+    require_all: bool,
+    probes: Vec<Probe>,
+}
+
+#[async_trait]
+impl HealthCheck for CompositeProbe {
+    async fn check_health(&self, endpoint: &Endpoint) -> Result<bool> {
+        let mut pending: FuturesUnordered<_> = self
+            .probes
+            .iter()
+            .map(|p| p.check_health(endpoint))
+            .collect();
+        let mut failures = Vec::new();
+        while let Some(result) = pending.next().await {
+            match reason(result) {
+                None if !self.require_all => return Ok(true),
+                Some(failure) if self.require_all => return Err(failed(failure)),
+                Some(failure) => failures.push(failure),
+                None => {}
+            }
+        }
+
+        if self.require_all {
+            Ok(true)
+        } else {
+            Err(failed(failures.join("; ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, routing::post, Json, Router};
+
+    async fn serve(app: Router) -> Endpoint {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        Endpoint::new(url, 1, 10)
+    }
+
+    /// An Ollama stand-in at version 0.1.30 with `llama3` installed, whose
+    /// generations take 200ms.
+    async fn ollama() -> Endpoint {
+        serve(
+            Router::new()
+                .route(
+                    "/api/version",
+                    get(|| async { Json(serde_json::json!({ "version": "0.1.30" })) }),
+                )
+                .route(
+                    "/api/tags",
+                    get(|| async {
+                        Json(serde_json::json!({
+                            "models": [{ "name": "llama3", "model": "llama3" }]
+                        }))
+                    }),
+                )
+                .route(
+                    "/api/generate",
+                    post(|Json(request): Json<serde_json::Value>| async move {
+                        assert_eq!(request["stream"], false);
+                        assert_eq!(request["options"]["num_predict"], 1);
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        Json(serde_json::json!({ "response": "Hi", "done": true }))
+                    }),
+                ),
+        )
+        .await
+    }
+
+    async fn check(config: ProbeConfig, endpoint: &Endpoint) -> std::result::Result<(), String> {
+        match config.build("llama3").check_health(endpoint).await {
+            Ok(true) => Ok(()),
+            other => Err(reason(other).unwrap()),
+        }
+    }
+
+    fn probe(yaml: &str) -> ProbeConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(compare_versions("0.1.32", "0.1.4"), Ordering::Greater);
+        assert_eq!(compare_versions("v0.5.0", "0.5"), Ordering::Equal);
+        assert_eq!(compare_versions("0.5.0-rc1", "0.5.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("2", "10"), Ordering::Less);
+    }
+
+    #[tokio::test]
+    async fn version_probe_enforces_the_minimum() {
+        let endpoint = ollama().await;
+        assert_eq!(check(probe("type: version"), &endpoint).await, Ok(()));
+        let at_least = |min: &str| probe(&format!("{{type: version, min_version: \"{}\"}}", min));
+        assert_eq!(check(at_least("0.1.30"), &endpoint).await, Ok(()));
+        assert_eq!(
+            check(at_least("0.1.32"), &endpoint).await,
+            Err("version 0.1.30 is older than 0.1.32".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn model_probe_looks_up_the_model() {
+        let endpoint = ollama().await;
+        assert_eq!(check(probe("type: model"), &endpoint).await, Ok(()));
+        assert_eq!(
+            check(probe("{type: model, model: mistral}"), &endpoint).await,
+            Err("model mistral not found".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn tcp_probe_needs_a_listener() {
+        let endpoint = ollama().await;
+        assert_eq!(check(ProbeConfig::Tcp, &endpoint).await, Ok(()));
+
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let error = check(ProbeConfig::Tcp, &Endpoint::new(url, 1, 10))
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("tcp connect to 127.0.0.1:"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn generate_probe_checks_the_latency_slo() {
+        let endpoint = ollama().await;
+        assert_eq!(check(probe("type: generate"), &endpoint).await, Ok(()));
+        assert_eq!(
+            check(probe("{type: generate, max_latency_ms: 5000}"), &endpoint).await,
+            Ok(())
+        );
+        let error = check(probe("{type: generate, max_latency_ms: 100}"), &endpoint)
+            .await
+            .unwrap_err();
+        assert!(error.ends_with("over the 100ms limit"), "{}", error);
+
+        let missing = check(
+            probe("{type: generate, model: mistral}"),
+            &serve(Router::new()).await,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            missing,
+            "generation with mistral returned status 404 Not Found"
+        );
+    }
+
+    /// Settles after `delay`, or never if it is `None`.
+    struct Fixed(Option<Duration>, bool);
+
+    #[async_trait]
+    impl HealthCheck for Fixed {
+        async fn check_health(&self, _endpoint: &Endpoint) -> Result<bool> {
+            match self.0 {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => std::future::pending().await,
+            }
+            if self.1 {
+                Ok(true)
+            } else {
+                Err(failed(format!("failed after {:?}", self.0.unwrap())))
+            }
+        }
+    }
+
+    fn composite(require_all: bool, probes: Vec<Fixed>) -> CompositeProbe {
+        CompositeProbe {
+            require_all,
+            probes: probes.into_iter().map(|p| Arc::new(p) as Probe).collect(),
+        }
+    }
+
+    async fn settle(probe: CompositeProbe) -> std::result::Result<(), String> {
+        let endpoint = Endpoint::new("http://gpu1:11434".to_string(), 1, 10);
+        let result = tokio::time::timeout(Duration::from_secs(1), probe.check_health(&endpoint))
+            .await
+            .expect("composite probe did not settle");
+        match result {
+            Ok(true) => Ok(()),
+            other => Err(reason(other).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn all_probes_must_pass_and_the_first_failure_decides() {
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(
+            settle(composite(
+                true,
+                vec![Fixed(ms(10), true), Fixed(ms(20), true)]
+            ))
+            .await,
+            Ok(())
+        );
+        assert_eq!(
+            settle(composite(
+                true,
+                vec![Fixed(None, true), Fixed(ms(10), false)]
+            ))
+            .await,
+            Err("failed after 10ms".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn any_probe_may_pass_and_the_first_success_decides() {
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(
+            settle(composite(
+                false,
+                vec![Fixed(None, false), Fixed(ms(10), true)]
+            ))
+            .await,
+            Ok(())
+        );
+        assert_eq!(
+            settle(composite(
+                false,
+                vec![Fixed(ms(20), false), Fixed(ms(10), false)]
+            ))
+            .await,
+            Err("failed after 10ms; failed after 20ms".to_string())
+        );
+    }
+
+    #[test]
+    fn composite_probes_nest() {
+        let config = probe(
+            r#"
+type: any
+probes:
+  - type: tcp
+  - type: all
+    probes: [{type: version, min_version: "0.1.30"}, {type: model}]
+"#,
+        );
+        assert_eq!(
+            config,
+            ProbeConfig::Any {
+                probes: vec![
+                    ProbeConfig::Tcp,
+                    ProbeConfig::All {
+                        probes: vec![
+                            ProbeConfig::Version {
+                                min_version: Some("0.1.30".to_string())
+                            },
+                            ProbeConfig::Model { model: None },
+                        ]
+                    },
+                ]
+            }
+        );
+    }
+}
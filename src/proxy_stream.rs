@@ -0,0 +1,380 @@
+use crate::endpoint::ConnectionGuard;
+use crate::metrics::Metrics;
+use crate::outlier::Outcome;
+use crate::routing::Route;
+use crate::timeouts::Timeouts;
+use crate::LoadBalancer;
+use bytes::Bytes;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Everything a streamed response needs to police timeouts and, for chat
+/// requests, re-issue the conversation elsewhere.
+pub struct StreamContext {
+    pub load_balancer: Arc<LoadBalancer>,
+    pub client: reqwest::Client,
+    pub path: String,
+    pub headers: reqwest::header::HeaderMap,
+    /// Connection on the endpoint currently serving the stream.
+    pub guard: ConnectionGuard,
+    pub timeouts: Timeouts,
+    /// When the request was received, for the total duration limit.
+    pub started: Instant,
+    /// Chat request to continue on another endpoint if the upstream fails
+    /// before the final chunk. `None` disables failover.
+    pub failover_request: Option<serde_json::Value>,
+    /// Label constraints of the request, kept when failing over.
+    pub route: Route,
+    pub max_failovers: u32,
+}
+
+/// Counts the request as cancelled by the client if dropped before being disarmed.
+/// Dropping the request future or stream that owns it also drops the upstream
+/// response, which closes that connection so Ollama stops generating.
+pub struct CancellationGuard {
+    metrics: Arc<Metrics>,
+    path: String,
+    stage: &'static str,
+    armed: bool,
+}
+
+impl CancellationGuard {
+    pub fn new(metrics: Arc<Metrics>, path: impl Into<String>, stage: &'static str) -> Self {
+        Self {
+            metrics,
+            path: path.into(),
+            stage,
+            armed: true,
+        }
+    }
+
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancellationGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            info!(
+                "Client disconnected from {} ({}), cancelling upstream request",
+                self.path, self.stage
+            );
+            self.metrics.increment_cancelled(self.stage);
+        }
+    }
+}
+
+struct ProxyStream {
+    // Declared first so the upstream connection is closed before the
+    // connection count on its endpoint is released
+    upstream: BoxStream<'static, reqwest::Result<Bytes>>,
+    ctx: StreamContext,
+    cancellation: CancellationGuard,
+    buffer: Vec<u8>,
+    content: String,
+    last_chunk: Option<Instant>,
+    upstream_started: Instant,
+    failovers: u32,
+    mark_next: bool,
+    done: bool,
+    finished: bool,
+}
+
+/// Relays an NDJSON upstream response line by line. A stalled or broken upstream
+/// is dropped (closing its connection), the endpoint penalized, and the client
+/// sent a terminal `{"error": ...}` chunk, unless the stream can fail over: then
+/// the partial answer is appended as an assistant message, the conversation
+/// re-issued to another endpoint, and the continuation stitched into the same
+/// client stream with a `failover` field on its first chunk.
+pub fn proxy_stream(
+    ctx: StreamContext,
+    upstream: reqwest::Response,
+) -> impl Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+    let cancellation = CancellationGuard::new(
+        ctx.load_balancer.get_metrics(),
+        ctx.path.clone(),
+        "streaming",
+    );
+    let state = ProxyStream {
+        upstream: upstream.bytes_stream().boxed(),
+        ctx,
+        cancellation,
+        buffer: Vec::new(),
+        content: String::new(),
+        last_chunk: None,
+        upstream_started: Instant::now(),
+        failovers: 0,
+        mark_next: false,
+        done: false,
+        finished: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        let chunk = state.next_chunk().await?;
+        Some((Ok(chunk), state))
+    })
+}
+
+enum Wait {
+    FirstByte,
+    Idle,
+    Total,
+}
+
+impl ProxyStream {
+    /// The next point at which the upstream is considered stalled, and why.
+    fn deadline(&self) -> Option<(Instant, Wait)> {
+        let timeouts = &self.ctx.timeouts;
+        let gap = match self.last_chunk {
+            None => timeouts
+                .first_byte
+                .map(|d| (self.upstream_started + d, Wait::FirstByte)),
+            Some(last) => timeouts.idle.map(|d| (last + d, Wait::Idle)),
+        };
+        let total = timeouts.total.map(|d| (self.ctx.started + d, Wait::Total));
+
+        match (gap, total) {
+            (Some(gap), Some(total)) => Some(if total.0 <= gap.0 { total } else { gap }),
+            (gap, total) => gap.or(total),
+        }
+    }
+
+    async fn next_chunk(&mut self) -> Option<Bytes> {
+        loop {
+            if self.finished {
+                return None;
+            }
+
+            let next = match self.deadline() {
+                Some((deadline, wait)) => {
+                    match tokio::time::timeout_at(deadline, self.upstream.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            let (reason, can_fail_over) = match wait {
+                                Wait::FirstByte => ("no first chunk in time", true),
+                                Wait::Idle => ("stalled between chunks", true),
+                                Wait::Total => ("total duration exceeded", false),
+                            };
+                            match self
+                                .fail(reason.to_string(), Outcome::Timeout, can_fail_over)
+                                .await
+                            {
+                                Ok(()) => continue,
+                                Err(message) => return Some(self.terminate(&message)),
+                            }
+                        }
+                    }
+                }
+                None => self.upstream.next().await,
+            };
+
+            let failure = match next {
+                Some(Ok(bytes)) => {
+                    self.last_chunk = Some(Instant::now());
+                    self.buffer.extend_from_slice(&bytes);
+                    match self.take_complete_lines() {
+                        Some(lines) => return Some(lines),
+                        None => continue,
+                    }
+                }
+                Some(Err(e)) => e.to_string(),
+                // The final chunk may come without a trailing newline
+                None if self.ctx.failover_request.is_some()
+                    && !self.buffer.is_empty()
+                    && serde_json::from_slice::<serde_json::Value>(&self.buffer).is_ok() =>
+                {
+                    self.buffer.push(b'\n');
+                    self.upstream = stream::empty().boxed();
+                    return self.take_complete_lines();
+                }
+                None if self.done || self.ctx.failover_request.is_none() => {
+                    self.finished = true;
+                    if self.buffer.is_empty() {
+                        return None;
+                    }
+                    return Some(Bytes::from(std::mem::take(&mut self.buffer)));
+                }
+                None => "stream ended before the final chunk".to_string(),
+            };
+
+            if let Err(message) = self.fail(failure, Outcome::Error, true).await {
+                return Some(self.terminate(&message));
+            }
+        }
+    }
+
+    fn terminate(&mut self, message: &str) -> Bytes {
+        warn!("Aborting stream for {}: {}", self.ctx.path, message);
+        self.finished = true;
+        self.upstream = stream::empty().boxed();
+        error_chunk(message)
+    }
+
+    /// Splits off every complete NDJSON line, tracking the streamed answer.
+    fn take_complete_lines(&mut self) -> Option<Bytes> {
+        let end = self.buffer.iter().rposition(|b| *b == b'\n')? + 1;
+        let complete: Vec<u8> = self.buffer.drain(..end).collect();
+        if self.ctx.failover_request.is_none() {
+            return Some(Bytes::from(complete));
+        }
+
+        let mut out = Vec::with_capacity(complete.len());
+        for line in complete.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            match serde_json::from_slice::<serde_json::Value>(line) {
+                Ok(mut chunk) => {
+                    if let Some(content) = chunk
+                        .get("message")
+                        .and_then(|m| m.get("content"))
+                        .and_then(|c| c.as_str())
+                    {
+                        self.content.push_str(content);
+                    }
+                    if chunk.get("done").and_then(|d| d.as_bool()) == Some(true) {
+                        self.done = true;
+                    }
+                    if self.mark_next {
+                        self.mark_next = false;
+                        if let Some(object) = chunk.as_object_mut() {
+                            object.insert(
+                                "failover".to_string(),
+                                serde_json::json!({ "count": self.failovers }),
+                            );
+                        }
+                        out.extend_from_slice(&serde_json::to_vec(&chunk).unwrap_or_default());
+                    } else {
+                        out.extend_from_slice(line);
+                    }
+                }
+                Err(_) => out.extend_from_slice(line),
+            }
+            out.push(b'\n');
+        }
+
+        Some(Bytes::from(out))
+    }
+
+    /// Penalizes the current endpoint and, where allowed, fails over to another one.
+    async fn fail(
+        &mut self,
+        reason: String,
+        outcome: Outcome,
+        can_fail_over: bool,
+    ) -> Result<(), String> {
+        let lb = self.ctx.load_balancer.clone();
+        lb.record_outcome(self.ctx.guard.endpoint(), outcome);
+        let mut reason = format!(
+            "{} failed mid-stream: {}",
+            self.ctx.guard.endpoint().url,
+            reason
+        );
+
+        let request = match &self.ctx.failover_request {
+            Some(request) if can_fail_over => request.clone(),
+            _ => return Err(reason),
+        };
+        let mut failed = vec![self.ctx.guard.endpoint().url.clone()];
+
+        while self.failovers < self.ctx.max_failovers {
+            self.failovers += 1;
+            warn!(
+                "{}, failing over ({} of {})",
+                reason, self.failovers, self.ctx.max_failovers
+            );
+            lb.get_metrics().increment_stream_failovers();
+
+            let model = request.get("model").and_then(|m| m.as_str());
+            let endpoint = lb
+                .select_endpoint(model, &failed, &self.ctx.route)
+                .await
+                .map_err(|e| format!("{}; no endpoint to fail over to: {}", reason, e))?;
+            let guard = endpoint.track_connection();
+            let request = crate::failover::continuation_request(&request, &self.content);
+
+            // The body changes length, so the client's Content-Length no longer applies
+            let mut headers = self.ctx.headers.clone();
+            headers.remove(reqwest::header::CONTENT_LENGTH);
+
+            let send = self
+                .ctx
+                .client
+                .post(format!("{}{}", endpoint.url, self.ctx.path))
+                .headers(headers)
+                .json(&request)
+                .send();
+            let result = match self.ctx.timeouts.first_byte {
+                Some(limit) => tokio::time::timeout(limit, send).await.map_err(|_| limit),
+                None => Ok(send.await),
+            };
+
+            match result {
+                Ok(Ok(response)) if response.status().is_success() && is_ndjson(&response) => {
+                    lb.record_outcome(&endpoint, Outcome::Success);
+                    self.upstream = response.bytes_stream().boxed();
+                    self.ctx.guard = guard;
+                    self.buffer.clear();
+                    self.last_chunk = None;
+                    self.upstream_started = Instant::now();
+                    self.mark_next = true;
+                    return Ok(());
+                }
+                Ok(Ok(response)) if response.status().is_success() => {
+                    lb.record_outcome(&endpoint, Outcome::Error);
+                    reason = format!("{} did not return an NDJSON stream", endpoint.url);
+                }
+                Ok(Ok(response)) => {
+                    lb.record_outcome(&endpoint, Outcome::Error);
+                    reason = format!("{} returned {}", endpoint.url, response.status());
+                }
+                Ok(Err(e)) => {
+                    let outcome = if e.is_timeout() {
+                        Outcome::Timeout
+                    } else {
+                        Outcome::Error
+                    };
+                    lb.record_outcome(&endpoint, outcome);
+                    reason = format!("{} failed: {}", endpoint.url, e);
+                }
+                Err(limit) => {
+                    lb.record_outcome(&endpoint, Outcome::Timeout);
+                    reason = format!(
+                        "{} sent no response within {}ms",
+                        endpoint.url,
+                        limit.as_millis()
+                    );
+                }
+            }
+            failed.push(endpoint.url);
+        }
+
+        Err(reason)
+    }
+}
+
+impl Drop for ProxyStream {
+    fn drop(&mut self) {
+        // Dropped mid-stream means the client went away
+        if self.finished {
+            self.cancellation.disarm();
+        }
+    }
+}
+
+/// Terminal chunk in Ollama's error format.
+/// Whether a response is a stream that can be spliced into the client's.
+fn is_ndjson(response: &reqwest::Response) -> bool {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("application/x-ndjson"))
+}
+
+pub fn error_chunk(message: &str) -> Bytes {
+    let mut chunk =
+        serde_json::to_vec(&serde_json::json!({ "error": message })).unwrap_or_default();
+    chunk.push(b'\n');
+    Bytes::from(chunk)
+}
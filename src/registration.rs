@@ -0,0 +1,272 @@
+use crate::config::{default_max_connections, default_weight, EndpointConfig};
+use crate::discovery::{Discovered, Discovery};
+use crate::error::{LoadBalancerError, Result};
+use crate::validation::{endpoint_url_problem, label_problem};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Name of the endpoint set holding self-registered nodes.
+const REGISTERED_SOURCE: &str = "registered";
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RegistrationConfig {
+    /// Accept `POST /admin/endpoints/register` from nodes.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Shared secret nodes send as `Authorization: Bearer <token>`; required
+    /// when registration is enabled.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Also serves the registration API, and nothing else, on this address so
+    /// nodes can reach it while the admin API stays on loopback.
+    #[serde(default)]
+    pub listen: Option<SocketAddr>,
+    /// How long a registration lasts without a heartbeat.
+    #[serde(default = "default_lease_seconds")]
+    pub lease_seconds: u64,
+}
+
+impl Default for RegistrationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            token: None,
+            listen: None,
+            lease_seconds: default_lease_seconds(),
+        }
+    }
+}
+
+fn default_lease_seconds() -> u64 {
+    30
+}
+
+/// What a node tells us about itself when registering.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Registration {
+    pub url: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Returned on registration and heartbeat: when the next heartbeat is due.
+#[derive(Debug, Serialize)]
+pub struct Lease {
+    pub url: String,
+    pub lease_seconds: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegisteredNode {
+    #[serde(flatten)]
+    pub registration: Registration,
+    pub expires_in_seconds: u64,
+}
+
+struct Node {
+    registration: Registration,
+    expires_at: Instant,
+}
+
+/// Nodes that registered themselves, each kept for one lease past its last
+/// heartbeat. Registered nodes become endpoints like any other and go through
+/// the same health checks.
+pub struct Registry {
+    config: RegistrationConfig,
+    nodes: Mutex<HashMap<String, Node>>,
+}
+
+impl Registry {
+    pub fn new(config: RegistrationConfig) -> Self {
+        Self {
+            config,
+            nodes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks the token a node sent against `registration.token`. Without a
+    /// configured token every node is turned away.
+    pub fn authorize(&self, token: Option<&str>) -> Result<()> {
+        match (&self.config.token, token) {
+            (Some(expected), Some(token)) if tokens_match(expected, token) => Ok(()),
+            _ => Err(LoadBalancerError::Unauthorized),
+        }
+    }
+
+    fn lease(&self) -> Duration {
+        Duration::from_secs(self.config.lease_seconds.max(1))
+    }
+
+    /// Adds a node, or renews and updates it if it is already registered.
+    pub fn register(&self, mut registration: Registration) -> Result<Lease> {
+        registration.url = registration.url.trim_end_matches('/').to_string();
+        if let Some(problem) =
+            endpoint_url_problem(&registration.url).or_else(|| label_problem(&registration.labels))
+        {
+            return Err(LoadBalancerError::BadRequest(problem));
+        }
+        if registration.weight == 0 {
+            return Err(LoadBalancerError::BadRequest(
+                "weight must be at least 1".to_string(),
+            ));
+        }
+
+        let url = registration.url.clone();
+        let node = Node {
+            registration,
+            expires_at: Instant::now() + self.lease(),
+        };
+        if self
+            .nodes
+            .lock()
+            .unwrap()
+            .insert(url.clone(), node)
+            .is_none()
+        {
+            info!("Registered {} for {}s", url, self.lease().as_secs());
+        }
+        Ok(Lease {
+            url,
+            lease_seconds: self.lease().as_secs(),
+        })
+    }
+
+    /// Extends a node's lease. A node whose lease already ran out has to
+    /// register again.
+    pub fn heartbeat(&self, url: &str) -> Result<Lease> {
+        let url = url.trim_end_matches('/');
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get_mut(url) {
+            Some(node) if node.expires_at > Instant::now() => {
+                node.expires_at = Instant::now() + self.lease();
+                Ok(Lease {
+                    url: url.to_string(),
+                    lease_seconds: self.lease().as_secs(),
+                })
+            }
+            _ => Err(LoadBalancerError::NotRegistered(url.to_string())),
+        }
+    }
+
+    pub fn deregister(&self, url: &str) -> Result<()> {
+        let url = url.trim_end_matches('/');
+        match self.nodes.lock().unwrap().remove(url) {
+            Some(_) => {
+                info!("Deregistered {}", url);
+                Ok(())
+            }
+            None => Err(LoadBalancerError::NotRegistered(url.to_string())),
+        }
+    }
+
+    pub fn nodes(&self) -> Vec<RegisteredNode> {
+        let now = Instant::now();
+        let mut nodes: Vec<RegisteredNode> = self
+            .nodes
+            .lock()
+            .unwrap()
+            .values()
+            .map(|node| RegisteredNode {
+                registration: node.registration.clone(),
+                expires_in_seconds: node.expires_at.saturating_duration_since(now).as_secs(),
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.registration.url.cmp(&b.registration.url));
+        nodes
+    }
+}
+
+/// Compares in time that depends only on the length, so a wrong token gives
+/// away nothing about the right one but its length.
+fn tokens_match(expected: &str, token: &str) -> bool {
+    expected.len() == token.len()
+        && expected
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[async_trait]
+impl Discovery for Registry {
+    fn source(&self) -> String {
+        REGISTERED_SOURCE.to_string()
+    }
+
+    /// Drops nodes whose lease ran out, and looks again when the next one
+    /// would.
+    async fn discover(&self) -> Result<Discovered> {
+        let now = Instant::now();
+        let mut nodes = self.nodes.lock().unwrap();
+        nodes.retain(|url, node| {
+            let alive = node.expires_at > now;
+            if !alive {
+                warn!("{} missed its heartbeats, removing it", url);
+            }
+            alive
+        });
+
+        let mut endpoints: Vec<EndpointConfig> = nodes
+            .values()
+            .map(|node| EndpointConfig {
+                url: node.registration.url.clone(),
+                dns: None,
+                weight: node.registration.weight,
+                max_connections: node.registration.max_connections,
+                labels: node.registration.labels.clone(),
+                health_check: Default::default(),
+            })
+            .collect();
+        endpoints.sort_by(|a, b| a.url.cmp(&b.url));
+        let next_expiry = nodes
+            .values()
+            .map(|node| node.expires_at.saturating_duration_since(now))
+            .min()
+            .unwrap_or(self.lease());
+        Ok(Discovered {
+            endpoints,
+            next_refresh: next_expiry.clamp(Duration::from_millis(100), self.lease()),
+        })
+    }
+
+    fn retry_interval(&self) -> Duration {
+        self.lease()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3cret", "s3creT"));
+        assert!(!tokens_match("s3cret", "s3cre"));
+        assert!(!tokens_match("s3cret", ""));
+    }
+
+    #[test]
+    fn nodes_need_the_configured_token() {
+        let registry = |token: Option<&str>| {
+            Registry::new(RegistrationConfig {
+                enabled: true,
+                token: token.map(str::to_string),
+                ..Default::default()
+            })
+        };
+        assert!(registry(Some("s3cret")).authorize(Some("s3cret")).is_ok());
+        assert!(registry(Some("s3cret")).authorize(Some("guess")).is_err());
+        assert!(registry(Some("s3cret")).authorize(None).is_err());
+        assert!(registry(None).authorize(Some("")).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::config::{ConfigLoader, EndpointConfig};
+use crate::{Config, EndpointChanges, LoadBalancer, Result};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time;
+use tracing::{error, info, warn};
+
+/// Re-reads the config file on SIGHUP or when it changes and applies the new
+/// endpoint list. Other settings only take effect on restart.
+pub struct ConfigReloader {
+    loader: ConfigLoader,
+    load_balancer: Arc<LoadBalancer>,
+    state: Mutex<ReloadState>,
+}
+
+struct ReloadState {
+    /// The running configuration, with the endpoints of the last good reload.
+    config: Config,
+    /// Everything but the endpoints as the server was started with, to warn
+    /// about edits that need a restart.
+    settings: serde_json::Value,
+    /// File contents last seen, so polling only reacts to edits.
+    contents: String,
+}
+
+impl ConfigReloader {
+    /// Reloads through the same layers as at startup, so environment and
+    /// command-line overrides keep applying.
+    pub fn new(loader: ConfigLoader, config: Config, load_balancer: Arc<LoadBalancer>) -> Self {
+        let contents = std::fs::read_to_string(loader.path()).unwrap_or_default();
+        let settings = without_endpoints(&loader, &contents);
+        Self {
+            loader,
+            load_balancer,
+            state: Mutex::new(ReloadState {
+                config,
+                settings,
+                contents,
+            }),
+        }
+    }
+
+    /// Loads the file and swaps in its endpoints. An invalid file is rejected
+    /// and the running configuration stays in place.
+    pub fn reload(&self) -> Result<EndpointChanges> {
+        let contents = std::fs::read_to_string(self.loader.path()).unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        state.contents = contents.clone();
+
+        let (new, report) = self.loader.load_checked()?;
+        for warning in report.into_result()? {
+            warn!("{}", warning);
+        }
+
+        if without_endpoints(&self.loader, &contents) != state.settings {
+            warn!(
+                "Settings other than endpoints changed in {}; they take effect on restart",
+                self.loader.path().display()
+            );
+        }
+
+        // DNS discovery runs for the entries the server started with
+        let discovered = |config: &Config| -> Vec<EndpointConfig> {
+            config
+                .endpoints
+                .iter()
+                .filter(|ec| ec.dns.is_some())
+                .cloned()
+                .collect()
+        };
+        if discovered(&new) != discovered(&state.config) {
+            warn!(
+                "DNS discovery entries changed in {}; they take effect on restart",
+                self.loader.path().display()
+            );
+        }
+
+        let mut config = state.config.clone();
+        config.endpoints = new.static_endpoints();
+        config.endpoints.extend(discovered(&state.config));
+        let changes = self.load_balancer.reload_endpoints(&config);
+        state.config = config;
+        Ok(changes)
+    }
+
+    /// Reloads on SIGHUP and, if `reload.watch` is set, whenever the file changes.
+    pub async fn run(self: Arc<Self>) {
+        let reload = self.state.lock().unwrap().config.reload.clone();
+        let poll_interval = Duration::from_secs(reload.poll_interval_seconds.max(1));
+        info!(
+            "Reloading {} on SIGHUP{}",
+            self.loader.path().display(),
+            if reload.watch { " or change" } else { "" }
+        );
+
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("Failed to install SIGHUP handler");
+
+        loop {
+            #[cfg(unix)]
+            let signal = hangup.recv();
+            #[cfg(not(unix))]
+            let signal = std::future::pending::<Option<()>>();
+
+            let poll = async {
+                if reload.watch {
+                    time::sleep(poll_interval).await
+                } else {
+                    std::future::pending().await
+                }
+            };
+
+            tokio::select! {
+                _ = signal => info!("Received SIGHUP, reloading {}", self.loader.path().display()),
+                _ = poll => {
+                    let contents = std::fs::read_to_string(self.loader.path()).unwrap_or_default();
+                    if contents == self.state.lock().unwrap().contents {
+                        continue;
+                    }
+                    info!("{} changed, reloading", self.loader.path().display());
+                }
+            }
+
+            match self.reload() {
+                Ok(changes) if changes.is_empty() => info!("Reloaded config, endpoints unchanged"),
+                Ok(changes) => info!(
+                    "Reloaded config: added {:?}, removed {:?}, updated {:?}",
+                    changes.added, changes.removed, changes.updated
+                ),
+                Err(e) => error!("Rejected new config, keeping the running one: {}", e),
+            }
+        }
+    }
+}
+
+fn without_endpoints(loader: &ConfigLoader, contents: &str) -> serde_json::Value {
+    let mut value = loader.parse_file(contents).unwrap_or_default();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("endpoints");
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(file: &str, contents: &str) -> serde_json::Value {
+        without_endpoints(&ConfigLoader::new(Some(file.into())), contents)
+    }
+
+    #[test]
+    fn settings_are_read_in_the_files_format() {
+        let expected = serde_json::json!({ "required_model": "llama3" });
+        assert_eq!(
+            settings(
+                "config.yaml",
+                "required_model: llama3\nendpoints:\n  - url: http://gpu1:11434\n"
+            ),
+            expected
+        );
+        assert_eq!(
+            settings(
+                "config.toml",
+                "required_model = \"llama3\"\n[[endpoints]]\nurl = \"http://gpu1:11434\"\n"
+            ),
+            expected
+        );
+        assert_eq!(
+            settings(
+                "config.json",
+                r#"{"required_model": "llama3", "endpoints": [{"url": "http://gpu1:11434"}]}"#
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn endpoint_edits_leave_the_settings_alone() {
+        let before = settings(
+            "config.toml",
+            "[[endpoints]]\nurl = \"http://gpu1:11434\"\n",
+        );
+        let after = settings(
+            "config.toml",
+            "[[endpoints]]\nurl = \"http://gpu2:11434\"\n",
+        );
+        assert_eq!(before, after);
+        assert_ne!(
+            before,
+            settings("config.toml", "required_model = \"llama3\"\n")
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::config::RetryConfig;
+use backoff::{ExponentialBackoff, ExponentialBackoffBuilder};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Token bucket that caps retries to a fraction of live traffic, so a failing
+/// fleet sees at most `1 + budget_ratio` times its normal request rate.
+pub struct RetryBudget {
+    tokens: Mutex<f64>,
+    ratio: f64,
+    capacity: f64,
+}
+
+impl RetryBudget {
+    pub fn new(config: &RetryConfig) -> Self {
+        let capacity = config.budget_reserve as f64;
+        Self {
+            tokens: Mutex::new(capacity),
+            ratio: config.budget_ratio,
+            capacity,
+        }
+    }
+
+    /// Credits the budget for an incoming request.
+    pub fn deposit(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.ratio).min(self.capacity);
+    }
+
+    /// Takes one retry out of the budget, returning false when it is exhausted.
+    pub fn try_withdraw(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap();
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoffBuilder::new()
+            .with_initial_interval(Duration::from_millis(self.initial_interval_ms))
+            .with_max_interval(Duration::from_millis(self.max_interval_ms))
+            .with_max_elapsed_time(None)
+            .build()
+    }
+}
+
+/// Upstream statuses that mean "try somewhere else" rather than a bad request.
+pub fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::BAD_GATEWAY || status == reqwest::StatusCode::SERVICE_UNAVAILABLE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backoff::backoff::Backoff;
+
+    fn config(budget_ratio: f64, budget_reserve: u32) -> RetryConfig {
+        RetryConfig {
+            max_attempts: 3,
+            initial_interval_ms: 100,
+            max_interval_ms: 1000,
+            budget_ratio,
+            budget_reserve,
+        }
+    }
+
+    #[test]
+    fn the_budget_starts_with_its_reserve() {
+        let budget = RetryBudget::new(&config(0.2, 2));
+        assert!(budget.try_withdraw());
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+    }
+
+    #[test]
+    fn requests_earn_retries_at_the_budget_ratio() {
+        let budget = RetryBudget::new(&config(0.2, 1));
+        assert!(budget.try_withdraw());
+        for _ in 0..4 {
+            budget.deposit();
+        }
+        assert!(!budget.try_withdraw());
+        budget.deposit();
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+    }
+
+    #[test]
+    fn saved_up_retries_are_capped_at_the_reserve() {
+        let budget = RetryBudget::new(&config(0.5, 2));
+        for _ in 0..100 {
+            budget.deposit();
+        }
+        assert!(budget.try_withdraw());
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+    }
+
+    #[test]
+    fn a_budget_without_reserve_allows_no_retries() {
+        let budget = RetryBudget::new(&config(0.5, 0));
+        budget.deposit();
+        budget.deposit();
+        assert!(!budget.try_withdraw());
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_max_interval() {
+        let mut backoff = config(0.2, 10).backoff();
+        let intervals: Vec<Duration> = (0..12).map(|_| backoff.next_backoff().unwrap()).collect();
+
+        // Intervals are randomized by up to 50% either way
+        assert!(intervals[0] >= Duration::from_millis(50));
+        assert!(intervals[0] <= Duration::from_millis(150));
+        assert!(intervals
+            .iter()
+            .all(|interval| *interval <= Duration::from_millis(1500)));
+        assert!(intervals[11] >= Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::error::{LoadBalancerError, Result};
+use crate::Endpoint;
+use http::HeaderMap;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoutingConfig {
+    /// Request header constraining which endpoints may serve the request by
+    /// their labels, e.g. `gpu=a100, tier!=spot, ~zone=rack2`.
+    #[serde(default = "default_route_header")]
+    pub header: String,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            header: default_route_header(),
+        }
+    }
+}
+
+impl RoutingConfig {
+    /// Reads the route constraint of a request. Requests without the header
+    /// may go to any endpoint.
+    pub fn route(&self, headers: &HeaderMap) -> Result<Route> {
+        let Some(value) = headers.get(self.header.as_str()) else {
+            return Ok(Route::default());
+        };
+        let value = value.to_str().map_err(|_| {
+            LoadBalancerError::BadRequest(format!("{} is not valid text", self.header))
+        })?;
+        value
+            .parse()
+            .map_err(|e| LoadBalancerError::BadRequest(format!("{}: {}", self.header, e)))
+    }
+}
+
+fn default_route_header() -> String {
+    "x-ollama-route".to_string()
+}
+
+/// A single `key=value` or `key!=value` condition on an endpoint label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelMatch {
+    pub key: String,
+    pub value: String,
+    pub negated: bool,
+}
+
+impl LabelMatch {
+    pub fn matches(&self, endpoint: &Endpoint) -> bool {
+        let equal = endpoint.labels.get(&self.key) == Some(&self.value);
+        equal != self.negated
+    }
+}
+
+impl fmt::Display for LabelMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = if self.negated { "!=" } else { "=" };
+        write!(f, "{}{}{}", self.key, op, self.value)
+    }
+}
+
+/// Label constraints of a request. Endpoints must satisfy every required
+/// match; among those, endpoints satisfying more preferred matches are tried
+/// first and the rest are only used when none of them is available.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Route {
+    pub required: Vec<LabelMatch>,
+    pub preferred: Vec<LabelMatch>,
+}
+
+impl Route {
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.preferred.is_empty()
+    }
+
+    /// Endpoints meeting the required matches, grouped by how many preferred
+    /// matches they meet, best group first. Fails if no endpoint meets the
+    /// required matches.
+    pub fn rank(&self, endpoints: &[Endpoint]) -> Result<Vec<Vec<Endpoint>>> {
+        let mut groups: Vec<Vec<Endpoint>> = vec![Vec::new(); self.preferred.len() + 1];
+        for endpoint in endpoints {
+            if !self.required.iter().all(|m| m.matches(endpoint)) {
+                continue;
+            }
+            let score = self
+                .preferred
+                .iter()
+                .filter(|m| m.matches(endpoint))
+                .count();
+            groups[self.preferred.len() - score].push(endpoint.clone());
+        }
+        groups.retain(|group| !group.is_empty());
+        if groups.is_empty() && !self.required.is_empty() {
+            return Err(LoadBalancerError::NoMatchingEndpoints(self.to_string()));
+        }
+        Ok(groups)
+    }
+}
+
+impl FromStr for Route {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut route = Route::default();
+        for term in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let (preferred, condition) = match term.strip_prefix('~') {
+                Some(condition) => (true, condition.trim_start()),
+                None => (false, term),
+            };
+            let (key, value, negated) = match condition.split_once("!=") {
+                Some((key, value)) => (key, value, true),
+                None => match condition.split_once('=') {
+                    Some((key, value)) => (key, value, false),
+                    None => return Err(format!("expected key=value, got {:?}", term)),
+                },
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() {
+                return Err(format!("missing label name in {:?}", term));
+            }
+            let label = LabelMatch {
+                key: key.to_string(),
+                value: value.to_string(),
+                negated,
+            };
+            if preferred {
+                route.preferred.push(label);
+            } else {
+                route.required.push(label);
+            }
+        }
+        Ok(route)
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms: Vec<String> = self
+            .required
+            .iter()
+            .map(ToString::to_string)
+            .chain(self.preferred.iter().map(|m| format!("~{}", m)))
+            .collect();
+        f.write_str(&terms.join(", "))
+    }
+}
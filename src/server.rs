@@ -0,0 +1,499 @@
+use crate::{
+    endpoint::ConnectionGuard,
+    failover::is_failover_candidate,
+    model_manager::ModelManager,
+    proxy_stream::{proxy_stream, CancellationGuard, StreamContext},
+    registration::Registry,
+    retry::is_retryable_status,
+    timeouts::{ClientPool, Timeouts},
+    CircuitState, Endpoint, HealthState, LoadBalancer, LoadBalancerError, Outcome, Route,
+};
+use axum::body::{to_bytes, Body};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use backoff::backoff::Backoff;
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue, Request, StatusCode};
+use http_body_util::StreamBody;
+use hyper::Method;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::UNIX_EPOCH,
+};
+use tokio::time::Instant;
+use tracing::warn;
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: String,
+    healthy_endpoints: Vec<EndpointHealth>,
+    total_endpoints: usize,
+    healthy_count: usize,
+}
+
+#[derive(Serialize)]
+struct EndpointHealth {
+    url: String,
+    labels: BTreeMap<String, String>,
+    healthy: bool,
+    state: HealthState,
+    state_since: Option<u64>,
+    state_reason: Option<String>,
+    draining: bool,
+    canary_failures: BTreeMap<String, String>,
+    effective_weight: f64,
+    ejected: bool,
+    circuit: CircuitState,
+    current_connections: u32,
+    model_available: bool,
+}
+
+// Custom error handling
+#[derive(Debug)]
+pub struct AppError(anyhow::Error);
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = if let Some(err) = self.0.downcast_ref::<LoadBalancerError>() {
+            match err {
+                LoadBalancerError::NoHealthyEndpoints => StatusCode::SERVICE_UNAVAILABLE,
+                LoadBalancerError::NoMatchingEndpoints(_) => StatusCode::SERVICE_UNAVAILABLE,
+                LoadBalancerError::LoadShed(_) => StatusCode::SERVICE_UNAVAILABLE,
+                LoadBalancerError::AtCapacity => StatusCode::SERVICE_UNAVAILABLE,
+                LoadBalancerError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+                LoadBalancerError::EndpointNotFound(_) => StatusCode::NOT_FOUND,
+                LoadBalancerError::EndpointExists(_) => StatusCode::CONFLICT,
+                LoadBalancerError::BadRequest(_) => StatusCode::BAD_REQUEST,
+                LoadBalancerError::RegistrationDisabled => StatusCode::FORBIDDEN,
+                LoadBalancerError::Unauthorized => StatusCode::UNAUTHORIZED,
+                LoadBalancerError::NotRegistered(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.0.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
+
+// Convert various error types to AppError
+impl<E> From<E> for AppError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(err: E) -> Self {
+        Self(err.into())
+    }
+}
+
+pub struct AppState {
+    pub load_balancer: Arc<LoadBalancer>,
+    pub required_model: String,
+    /// Set when nodes may register themselves.
+    pub registry: Option<Arc<Registry>>,
+    pub(crate) pulls: crate::admin::ModelPulls,
+    clients: ClientPool,
+    shutting_down: AtomicBool,
+}
+
+impl AppState {
+    pub fn new(load_balancer: Arc<LoadBalancer>, required_model: String) -> Self {
+        Self {
+            load_balancer,
+            required_model,
+            registry: None,
+            pulls: Default::default(),
+            clients: ClientPool::new(),
+            shutting_down: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_registry(mut self, registry: Arc<Registry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Fails readiness so `/health` tells load balancers in front of us to stop
+    /// sending traffic while in-flight requests drain.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+}
+
+/// The proxy's HTTP routes: `/health` and everything else forwarded upstream.
+/// The admin API is served separately by `admin::router`.
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/health", get(handle_health_check))
+        .fallback(handle_proxy)
+        .layer(tower_http::trace::TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+async fn verify_model_availability(endpoint: &Endpoint, model_name: &str) -> Result<(), AppError> {
+    let model_manager = ModelManager::new();
+    if !model_manager.is_model_present(endpoint, model_name).await? {
+        return Err(LoadBalancerError::ConfigError(format!(
+            "Required model {} is not available on endpoint {}",
+            model_name, endpoint.url
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// What the endpoint serving a request is picked by.
+struct Placement<'a> {
+    model: Option<&'a str>,
+    route: &'a Route,
+}
+
+/// Sends the buffered request, moving it to an endpoint it has not tried yet
+/// with exponential backoff on connect errors or a 502/503. Retries only ever happen here, before the
+/// upstream response is handed to the client, so no streamed bytes are replayed.
+async fn send_with_retry(
+    state: &AppState,
+    method: reqwest::Method,
+    path: &str,
+    headers: reqwest::header::HeaderMap,
+    body: Option<Bytes>,
+    placement: Placement<'_>,
+    timeouts: &Timeouts,
+) -> Result<(reqwest::Response, ConnectionGuard), AppError> {
+    let Placement { model, route } = placement;
+    let lb = &state.load_balancer;
+    let retry = lb.retry_config();
+    let mut backoff = retry.backoff();
+    let mut tried: Vec<String> = Vec::new();
+    let client = state.clients.get(timeouts.connect);
+
+    lb.retry_budget().deposit();
+
+    let mut attempt = 1;
+    let mut endpoint = lb.get_endpoint(model, route).await?;
+    loop {
+        let guard = endpoint.track_connection();
+
+        // Verify model availability before processing the request
+        let (result, retryable) =
+            match verify_model_availability(&endpoint, &state.required_model).await {
+                Err(e) => {
+                    lb.record_outcome(&endpoint, Outcome::Error);
+                    (Err(e), true)
+                }
+                Ok(()) => {
+                    let mut client_req = client
+                        .request(method.clone(), format!("{}{}", endpoint.url, path))
+                        .headers(headers.clone());
+                    if let Some(body) = &body {
+                        client_req = client_req.body(body.clone());
+                    }
+
+                    let sent = match timeouts.first_byte {
+                        Some(limit) => tokio::time::timeout(limit, client_req.send())
+                            .await
+                            .map_err(|_| limit),
+                        None => Ok(client_req.send().await),
+                    };
+
+                    match sent.map_err(|limit| (endpoint.url.clone(), limit)) {
+                        // A slow model load is not worth replaying elsewhere
+                        Err((url, limit)) => {
+                            lb.record_outcome(&endpoint, Outcome::Timeout);
+                            let error = LoadBalancerError::Timeout(format!(
+                                "no response from {} within {}ms",
+                                url,
+                                limit.as_millis()
+                            ));
+                            (Err(error.into()), false)
+                        }
+                        Ok(Ok(response)) => {
+                            let outcome = if response.status().is_server_error() {
+                                Outcome::Error
+                            } else {
+                                Outcome::Success
+                            };
+                            lb.record_outcome(&endpoint, outcome);
+                            let retryable = is_retryable_status(response.status());
+                            (Ok(response), retryable)
+                        }
+                        Ok(Err(e)) => {
+                            let outcome = if e.is_timeout() {
+                                Outcome::Timeout
+                            } else {
+                                Outcome::Error
+                            };
+                            lb.record_outcome(&endpoint, outcome);
+                            let retryable = e.is_connect();
+                            (Err(e.into()), retryable)
+                        }
+                    }
+                }
+            };
+
+        if !retryable || attempt >= retry.max_attempts {
+            return result.map(|response| (response, guard));
+        }
+
+        match &result {
+            Ok(response) => warn!(
+                "Attempt {} on {} returned {}",
+                attempt,
+                endpoint.url,
+                response.status()
+            ),
+            Err(e) => warn!("Attempt {} on {} failed: {}", attempt, endpoint.url, e.0),
+        }
+
+        if !lb.retry_budget().try_withdraw() {
+            warn!("Retry budget exhausted, giving up on request to {}", path);
+            lb.get_metrics().increment_retry_budget_exhausted();
+            return result.map(|response| (response, guard));
+        }
+
+        // Endpoints that already failed this request are not tried again, so
+        // once every available endpoint has had a go the last answer stands
+        tried.push(endpoint.url.clone());
+        endpoint = match lb.select_endpoint(model, &tried, route).await {
+            Ok(next) => next,
+            Err(e) => {
+                warn!("No endpoint left to retry request to {} on: {}", path, e);
+                return result.map(|response| (response, guard));
+            }
+        };
+
+        lb.get_metrics().increment_retries();
+        attempt += 1;
+        tokio::time::sleep(backoff.next_backoff().unwrap_or_default()).await;
+    }
+}
+
+async fn handle_proxy(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+) -> Result<Response, AppError> {
+    // Axum drops this future when the client disconnects, taking any in-flight
+    // upstream request with it; streamed bodies carry their own guard from here
+    let mut cancellation = CancellationGuard::new(
+        state.load_balancer.get_metrics(),
+        req.uri().path(),
+        "before_response",
+    );
+    let result = proxy_request(state, req).await;
+    cancellation.disarm();
+    result
+}
+
+async fn proxy_request(state: Arc<AppState>, req: Request<Body>) -> Result<Response, AppError> {
+    let started = Instant::now();
+
+    // Reject a malformed route before the request is queued
+    let route = state.load_balancer.routing_config().route(req.headers())?;
+
+    // Hold back or shed low-priority traffic before an endpoint is picked
+    let priority = state
+        .load_balancer
+        .priority_config()
+        .classify(req.headers());
+    state.load_balancer.admit(priority).await?;
+
+    // Build the forwarding path
+    let req_path = req.uri().path().to_string();
+    let query = req
+        .uri()
+        .query()
+        .map_or_else(String::new, |q| format!("?{}", q));
+    let path = format!("{}{}", req_path, query);
+    let req_path = req_path.as_str();
+    let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())?;
+
+    // Convert headers
+    let mut reqwest_headers = reqwest::header::HeaderMap::new();
+    for (name, value) in req.headers() {
+        if name.as_str().to_lowercase() != "host" {
+            if let Ok(value) = reqwest::header::HeaderValue::from_bytes(value.as_bytes()) {
+                reqwest_headers
+                    .insert(reqwest::header::HeaderName::from_str(name.as_str())?, value);
+            }
+        }
+    }
+
+    // Buffer the body for POST/PUT requests so it can be replayed on retry
+    let body = if req.method() == Method::POST || req.method() == Method::PUT {
+        Some(to_bytes(req.into_body(), 32 * 1024 * 1024).await?)
+    } else {
+        None
+    };
+
+    let request_json = body
+        .as_ref()
+        .and_then(|body| serde_json::from_slice::<serde_json::Value>(body).ok());
+    let model = request_json
+        .as_ref()
+        .and_then(|request| request.get("model"))
+        .and_then(|model| model.as_str())
+        .map(str::to_string);
+    let timeouts = state
+        .load_balancer
+        .timeout_config()
+        .resolve(req_path, model.as_deref());
+
+    // Keep the parsed chat request around in case the stream has to fail over
+    let failover_request = request_json
+        .filter(|_| state.load_balancer.stream_failover_config().enabled)
+        .filter(|request| is_failover_candidate(req_path, request));
+
+    // Send the request
+    let (response, guard) = send_with_retry(
+        &state,
+        method,
+        &path,
+        reqwest_headers.clone(),
+        body,
+        Placement {
+            model: model.as_deref(),
+            route: &route,
+        },
+        &timeouts,
+    )
+    .await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+
+    // Get content type
+    let is_stream = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("application/x-ndjson"));
+
+    if is_stream {
+        let ctx = StreamContext {
+            load_balancer: state.load_balancer.clone(),
+            client: state.clients.get(timeouts.connect),
+            path,
+            headers: reqwest_headers,
+            guard,
+            timeouts,
+            started,
+            failover_request,
+            route,
+            max_failovers: state.load_balancer.stream_failover_config().max_failovers,
+        };
+
+        // Use StreamBody from http_body_util and wrap it with Axum's Body
+        let body = Body::from_stream(StreamBody::new(proxy_stream(ctx, response)));
+
+        // Return streaming response
+        Ok(Response::builder()
+            .status(StatusCode::from_u16(status.as_u16())?)
+            .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body)?) // Ensure body is compatible with axum::body::Body
+    } else {
+        // Handle non-streaming response
+        let body_bytes = match timeouts.total {
+            Some(limit) => match tokio::time::timeout_at(started + limit, response.bytes()).await {
+                Ok(body_bytes) => body_bytes?,
+                Err(_) => {
+                    state
+                        .load_balancer
+                        .record_outcome(guard.endpoint(), Outcome::Timeout);
+                    return Err(LoadBalancerError::Timeout(format!(
+                        "response from {} exceeded {}ms",
+                        guard.endpoint().url,
+                        limit.as_millis()
+                    ))
+                    .into());
+                }
+            },
+            None => response.bytes().await?,
+        };
+        drop(guard);
+        let mut builder = Response::builder().status(StatusCode::from_u16(status.as_u16())?);
+        for (name, value) in headers {
+            if let Some(name) = name {
+                if let Ok(header_name) = HeaderName::from_str(name.as_str()) {
+                    if let Ok(header_value) = HeaderValue::from_bytes(value.as_bytes()) {
+                        builder = builder.header(header_name, header_value);
+                    }
+                }
+            }
+        }
+        Ok(builder.body(Body::from(body_bytes))?) // Ensure body is of type axum::body::Body
+    }
+}
+
+async fn handle_health_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let endpoints = state.load_balancer.endpoints();
+    let model_manager = ModelManager::new();
+
+    let mut endpoint_health = Vec::new();
+
+    for endpoint in endpoints.iter() {
+        let model_status = model_manager
+            .is_model_present(endpoint, &state.required_model)
+            .await
+            .unwrap_or(false);
+
+        let transition = endpoint.last_health_transition();
+        endpoint_health.push(EndpointHealth {
+            url: endpoint.url.clone(),
+            labels: endpoint.labels.clone(),
+            healthy: endpoint.is_healthy(),
+            state: endpoint.health_state(),
+            state_since: transition.as_ref().and_then(|t| {
+                t.at.duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|since| since.as_secs())
+            }),
+            state_reason: transition.map(|t| t.reason),
+            draining: endpoint.is_draining(),
+            canary_failures: endpoint.canary_failures(),
+            effective_weight: endpoint.effective_weight(),
+            ejected: endpoint.is_ejected(),
+            circuit: endpoint.circuit_state(),
+            current_connections: endpoint.get_connections(),
+            model_available: model_status,
+        });
+    }
+
+    let healthy_count = endpoint_health
+        .iter()
+        .filter(|ep| ep.healthy && ep.model_available && !ep.draining)
+        .count();
+
+    let (status, code) = if state.is_shutting_down() {
+        ("SHUTTING_DOWN", StatusCode::SERVICE_UNAVAILABLE)
+    } else if healthy_count > 0 {
+        ("OK", StatusCode::OK)
+    } else {
+        ("UNHEALTHY", StatusCode::SERVICE_UNAVAILABLE)
+    };
+
+    let response = HealthResponse {
+        status: status.to_string(),
+        healthy_endpoints: endpoint_health,
+        total_endpoints: endpoints.len(),
+        healthy_count,
+    };
+
+    (code, Json(response))
+}
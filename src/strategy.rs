@@ -5,4 +5,8 @@ use async_trait::async_trait;
 #[async_trait]
 pub trait LoadBalancingStrategy: Send + Sync {
     async fn next_endpoint<'a>(&self, endpoints: &'a [Endpoint]) -> Result<&'a Endpoint>;
+
+    /// Called with the URLs that left the endpoint set, so per-endpoint state
+    /// can be dropped.
+    fn endpoints_removed(&self, _urls: &[String]) {}
 }
@@ -0,0 +1,166 @@
+use dashmap::DashMap;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TimeoutConfig {
+    #[serde(default = "default_connect_ms")]
+    pub connect_ms: Option<u64>,
+    /// Time allowed for the response headers, and then for the first body chunk.
+    #[serde(default)]
+    pub first_byte_ms: Option<u64>,
+    /// Longest gap allowed between two chunks of a streamed response.
+    #[serde(default)]
+    pub idle_ms: Option<u64>,
+    #[serde(default)]
+    pub total_ms: Option<u64>,
+    /// Per route and/or per model overrides; the first matching rule wins.
+    #[serde(default)]
+    pub overrides: Vec<TimeoutOverride>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TimeoutOverride {
+    pub path: Option<String>,
+    pub model: Option<String>,
+    pub connect_ms: Option<u64>,
+    pub first_byte_ms: Option<u64>,
+    pub idle_ms: Option<u64>,
+    pub total_ms: Option<u64>,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect_ms: default_connect_ms(),
+            first_byte_ms: None,
+            idle_ms: None,
+            total_ms: None,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+fn default_connect_ms() -> Option<u64> {
+    Some(10_000)
+}
+
+/// Timeouts resolved for a single request.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub first_byte: Option<Duration>,
+    pub idle: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl TimeoutConfig {
+    pub fn resolve(&self, path: &str, model: Option<&str>) -> Timeouts {
+        let rule = self.overrides.iter().find(|rule| {
+            !matches!(rule.path.as_deref(), Some(p) if p != path)
+                && !matches!(rule.model.as_deref(), Some(m) if Some(m) != model)
+        });
+
+        let pick = |base: Option<u64>, over: Option<Option<u64>>| {
+            over.flatten().or(base).map(Duration::from_millis)
+        };
+        Timeouts {
+            connect: pick(self.connect_ms, rule.map(|r| r.connect_ms)),
+            first_byte: pick(self.first_byte_ms, rule.map(|r| r.first_byte_ms)),
+            idle: pick(self.idle_ms, rule.map(|r| r.idle_ms)),
+            total: pick(self.total_ms, rule.map(|r| r.total_ms)),
+        }
+    }
+}
+
+/// HTTP clients keyed by connect timeout, since reqwest only sets it per client.
+#[derive(Default)]
+pub struct ClientPool {
+    clients: DashMap<Option<Duration>, reqwest::Client>,
+}
+
+impl ClientPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, connect_timeout: Option<Duration>) -> reqwest::Client {
+        self.clients
+            .entry(connect_timeout)
+            .or_insert_with(|| {
+                let mut builder = reqwest::Client::builder();
+                if let Some(timeout) = connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                builder.build().expect("Failed to create HTTP client")
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path: Option<&str>, model: Option<&str>, total_ms: u64) -> TimeoutOverride {
+        TimeoutOverride {
+            path: path.map(str::to_string),
+            model: model.map(str::to_string),
+            connect_ms: None,
+            first_byte_ms: None,
+            idle_ms: None,
+            total_ms: Some(total_ms),
+        }
+    }
+
+    #[test]
+    fn defaults_apply_without_overrides() {
+        let timeouts = TimeoutConfig::default().resolve("/api/generate", Some("llama3"));
+        assert_eq!(timeouts.connect, Some(Duration::from_secs(10)));
+        assert_eq!(timeouts.first_byte, None);
+        assert_eq!(timeouts.idle, None);
+        assert_eq!(timeouts.total, None);
+    }
+
+    #[test]
+    fn first_matching_override_wins() {
+        let config = TimeoutConfig {
+            idle_ms: Some(500),
+            overrides: vec![
+                rule(Some("/api/embed"), Some("nomic"), 1_000),
+                rule(Some("/api/embed"), None, 2_000),
+                rule(None, Some("nomic"), 3_000),
+            ],
+            ..TimeoutConfig::default()
+        };
+        let total = |path, model| config.resolve(path, model).total;
+
+        assert_eq!(
+            total("/api/embed", Some("nomic")),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            total("/api/embed", Some("llama3")),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(total("/api/embed", None), Some(Duration::from_secs(2)));
+        assert_eq!(
+            total("/api/chat", Some("nomic")),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(total("/api/chat", None), None);
+    }
+
+    #[test]
+    fn unset_override_fields_keep_the_base() {
+        let config = TimeoutConfig {
+            idle_ms: Some(500),
+            overrides: vec![rule(None, None, 1_000)],
+            ..TimeoutConfig::default()
+        };
+        let timeouts = config.resolve("/api/chat", None);
+        assert_eq!(timeouts.connect, Some(Duration::from_secs(10)));
+        assert_eq!(timeouts.idle, Some(Duration::from_millis(500)));
+        assert_eq!(timeouts.total, Some(Duration::from_secs(1)));
+    }
+}
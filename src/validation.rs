@@ -0,0 +1,866 @@
+use crate::canary::CanaryExpectation;
+use crate::config::{Config, EndpointConfig, ReloadConfig, SlowStartConfig};
+use crate::dns::DnsDiscoveryConfig;
+use crate::priority::ClassPolicy;
+use crate::probes::ProbeConfig;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The config is rejected.
+    Error,
+    /// The config loads, but a setting does nothing.
+    Warning,
+}
+
+/// One problem found in a config, located by its path in the document,
+/// e.g. `endpoints[2].weight`.
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, " (hint: {})", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every problem found in a config, in document order.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> impl Iterator<Item = &ConfigIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ConfigIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// The warnings if there are no errors, otherwise the whole report as an error.
+    pub fn into_result(self) -> crate::Result<Vec<ConfigIssue>> {
+        if self.has_errors() {
+            return Err(crate::LoadBalancerError::InvalidConfig(self));
+        }
+        Ok(self.issues)
+    }
+
+    pub(crate) fn error(
+        &mut self,
+        path: impl Into<String>,
+        message: impl Into<String>,
+    ) -> &mut ConfigIssue {
+        self.push(Severity::Error, path.into(), message.into())
+    }
+
+    pub(crate) fn warning(
+        &mut self,
+        path: impl Into<String>,
+        message: impl Into<String>,
+    ) -> &mut ConfigIssue {
+        self.push(Severity::Warning, path.into(), message.into())
+    }
+
+    fn push(&mut self, severity: Severity, path: String, message: String) -> &mut ConfigIssue {
+        self.issues.push(ConfigIssue {
+            severity,
+            path,
+            message,
+            hint: None,
+        });
+        self.issues.last_mut().unwrap()
+    }
+}
+
+impl ConfigIssue {
+    pub(crate) fn hint(&mut self, hint: impl Into<String>) {
+        self.hint = Some(hint.into());
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, issue) in self.errors().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the settings of a parsed config. Unknown fields are reported by
+/// `ConfigLoader`, which sees the raw document.
+pub fn validate(config: &Config) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    validate_endpoints(config, &mut report);
+    validate_health_check(config, &mut report);
+
+    if !crate::lb::STRATEGIES.contains(&config.strategy.as_str()) {
+        report
+            .error(
+                "strategy",
+                format!("unknown strategy {:?}", config.strategy),
+            )
+            .hint(format!("use one of {}", crate::lb::STRATEGIES.join(", ")));
+    }
+    if config.required_model.trim().is_empty() {
+        report
+            .error("required_model", "must not be empty")
+            .hint("name the model every endpoint must serve, e.g. llama3.2");
+    }
+    if config.server.admin_listen == config.server.listen {
+        report
+            .error("server.admin_listen", "must differ from server.listen")
+            .hint("the admin API is always served on its own address, e.g. 127.0.0.1:3001");
+    } else if config.server.admin_listen.ip().is_unspecified() {
+        report
+            .warning(
+                "server.admin_listen",
+                "exposes the unauthenticated admin API on every interface",
+            )
+            .hint("bind it to loopback or a management-only address");
+    }
+    if config.server.log_level.parse::<tracing::Level>().is_err() {
+        report
+            .error(
+                "server.log_level",
+                format!("unknown log level {:?}", config.server.log_level),
+            )
+            .hint("use one of trace, debug, info, warn or error");
+    }
+
+    if http::HeaderName::from_bytes(config.routing.header.as_bytes()).is_err() {
+        report
+            .error(
+                "routing.header",
+                format!("{:?} is not a valid header name", config.routing.header),
+            )
+            .hint("use a name such as x-ollama-route");
+    }
+
+    validate_retry(config, &mut report);
+    validate_priority(config, &mut report);
+    validate_resilience(config, &mut report);
+    validate_canary(config, &mut report);
+    validate_discovery(config, &mut report);
+    validate_registration(config, &mut report);
+    validate_misc(config, &mut report);
+    report
+}
+
+fn validate_endpoints(config: &Config, report: &mut ValidationReport) {
+    if config.endpoints.is_empty()
+        && config.discovery.files.is_empty()
+        && !config.registration.enabled
+    {
+        report
+            .error("endpoints", "at least one endpoint is required")
+            .hint("list endpoints, set OLLAMA_MANAGER_ENDPOINTS, add discovery.files or enable registration");
+    }
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (i, endpoint) in config.endpoints.iter().enumerate() {
+        let path = format!("endpoints[{}]", i);
+        if let Some(dns) = &endpoint.dns {
+            if !endpoint.url.is_empty() {
+                report
+                    .error(format!("{}.url", path), "cannot be combined with dns")
+                    .hint("list fixed endpoints and DNS names as separate entries");
+            }
+            validate_dns(&format!("{}.dns", path), dns, report);
+        } else if endpoint.url.is_empty() {
+            report
+                .error(format!("{}.url", path), "is required")
+                .hint("set url, or dns to discover the endpoints");
+        } else {
+            validate_url(&path, endpoint, &mut seen, i, report);
+        }
+        validate_endpoint_limits(&path, endpoint, report);
+    }
+}
+
+fn validate_dns(path: &str, dns: &DnsDiscoveryConfig, report: &mut ValidationReport) {
+    match (&dns.host, &dns.srv) {
+        (Some(_), Some(_)) => {
+            report
+                .error(format!("{}.srv", path), "cannot be combined with host")
+                .hint("use host for A/AAAA records or srv for an SRV record");
+        }
+        (None, None) => {
+            report
+                .error(path, "needs a host or srv name")
+                .hint("e.g. host: ollama.internal, or srv: _ollama._tcp.internal");
+        }
+        _ => {}
+    }
+    if dns.scheme != "http" && dns.scheme != "https" {
+        report
+            .error(
+                format!("{}.scheme", path),
+                format!("unsupported scheme {:?}", dns.scheme),
+            )
+            .hint("use http or https");
+    }
+    if dns.min_refresh_seconds == 0 {
+        report
+            .error(
+                format!("{}.min_refresh_seconds", path),
+                "must be at least 1",
+            )
+            .hint("a record with a TTL of 0 would otherwise be re-resolved continuously");
+    }
+    if dns.min_refresh_seconds > dns.max_refresh_seconds {
+        report
+            .error(
+                format!("{}.min_refresh_seconds", path),
+                format!(
+                    "is longer than max_refresh_seconds ({})",
+                    dns.max_refresh_seconds
+                ),
+            )
+            .hint("raise max_refresh_seconds or lower min_refresh_seconds");
+    }
+}
+
+fn validate_url<'a>(
+    path: &str,
+    endpoint: &'a EndpointConfig,
+    seen: &mut HashMap<&'a str, usize>,
+    i: usize,
+    report: &mut ValidationReport,
+) {
+    match reqwest::Url::parse(&endpoint.url) {
+        Ok(url) if url.scheme() != "http" && url.scheme() != "https" => {
+            report
+                .error(
+                    format!("{}.url", path),
+                    format!("unsupported scheme {:?}", url.scheme()),
+                )
+                .hint("use http:// or https://");
+        }
+        Ok(url) if url.host().is_none() => {
+            report
+                .error(format!("{}.url", path), "has no host")
+                .hint("use a URL like http://gpu1:11434");
+        }
+        Ok(_) => {}
+        Err(e) => {
+            report
+                .error(
+                    format!("{}.url", path),
+                    format!("invalid URL {:?}: {}", endpoint.url, e),
+                )
+                .hint("use a URL like http://gpu1:11434");
+        }
+    }
+    if let Some(first) = seen.insert(endpoint.url.trim_end_matches('/'), i) {
+        report
+            .error(
+                format!("{}.url", path),
+                format!("{} is already listed as endpoints[{}]", endpoint.url, first),
+            )
+            .hint("remove the duplicate, or raise the weight of the first entry");
+    }
+}
+
+/// Why `url` cannot be proxied to, for endpoints coming from outside the
+/// config file.
+pub(crate) fn endpoint_url_problem(url: &str) -> Option<String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() != "http" && parsed.scheme() != "https" => {
+            Some(format!("unsupported scheme {:?}", parsed.scheme()))
+        }
+        Ok(parsed) if parsed.host().is_none() => Some(format!("{:?} has no host", url)),
+        Ok(_) => None,
+        Err(e) => Some(format!("invalid URL {:?}: {}", url, e)),
+    }
+}
+
+/// Why a label cannot be matched by a route header, if it can't.
+pub(crate) fn label_problem(labels: &BTreeMap<String, String>) -> Option<String> {
+    labels.keys().find_map(|key| {
+        let unusable = key.is_empty()
+            || key.starts_with('~')
+            || key
+                .chars()
+                .any(|c| c.is_whitespace() || matches!(c, '=' | '!' | ','));
+        unusable.then(|| format!("label {:?} cannot be matched by a route", key))
+    })
+}
+
+fn validate_endpoint_limits(path: &str, endpoint: &EndpointConfig, report: &mut ValidationReport) {
+    if let Some(problem) = label_problem(&endpoint.labels) {
+        report
+            .error(format!("{}.labels", path), problem)
+            .hint("label names must not be empty, start with ~ or contain whitespace, =, ! or ,");
+    }
+    if endpoint.weight == 0 {
+        report
+            .error(format!("{}.weight", path), "must be at least 1")
+            .hint("drain the endpoint through the admin API to take it out of rotation");
+    }
+    if endpoint.max_connections == 0 {
+        report
+            .error(format!("{}.max_connections", path), "must be at least 1")
+            .hint("an endpoint with no connection slots can never be used; remove it instead");
+    }
+    if endpoint.health_check.interval_seconds == Some(0) {
+        report
+            .error(
+                format!("{}.health_check.interval_seconds", path),
+                "must be at least 1",
+            )
+            .hint("leave it out to use the global health_check.interval_seconds");
+    }
+    if endpoint.health_check.timeout_seconds == Some(0) {
+        report
+            .error(
+                format!("{}.health_check.timeout_seconds", path),
+                "must be at least 1",
+            )
+            .hint("leave it out to use the global health_check.timeout_seconds");
+    }
+    if let Some(probe) = &endpoint.health_check.probe {
+        validate_probe(&format!("{}.health_check.probe", path), probe, report);
+    }
+}
+
+fn validate_probe(path: &str, probe: &ProbeConfig, report: &mut ValidationReport) {
+    let (probes, outcome) = match probe {
+        ProbeConfig::All { probes } => (probes, "always passes"),
+        ProbeConfig::Any { probes } => (probes, "always fails"),
+        _ => return,
+    };
+    if probes.is_empty() {
+        report
+            .error(
+                format!("{}.probes", path),
+                format!("is empty, so the probe {}", outcome),
+            )
+            .hint("list at least one probe, or use that probe's type directly");
+    }
+    for (i, probe) in probes.iter().enumerate() {
+        validate_probe(&format!("{}.probes[{}]", path, i), probe, report);
+    }
+}
+
+fn validate_health_check(config: &Config, report: &mut ValidationReport) {
+    let health_check = &config.health_check;
+    for (field, value) in [
+        ("interval_seconds", health_check.interval_seconds),
+        ("timeout_seconds", health_check.timeout_seconds),
+        ("healthy_threshold", health_check.healthy_threshold as u64),
+        (
+            "unhealthy_threshold",
+            health_check.unhealthy_threshold as u64,
+        ),
+    ] {
+        if value == 0 {
+            report
+                .error(format!("health_check.{}", field), "must be at least 1")
+                .hint("1 acts on a single probe result");
+        }
+    }
+    match health_check.unhealthy_interval_seconds {
+        Some(0) => {
+            report
+                .error(
+                    "health_check.unhealthy_interval_seconds",
+                    "must be at least 1",
+                )
+                .hint("leave it out to probe unhealthy endpoints at the normal interval");
+        }
+        Some(faster) if faster >= health_check.interval_seconds => {
+            report
+                .warning(
+                    "health_check.unhealthy_interval_seconds",
+                    format!(
+                        "has no effect: it is not shorter than interval_seconds ({})",
+                        health_check.interval_seconds
+                    ),
+                )
+                .hint("set it below interval_seconds or remove it");
+        }
+        _ => {}
+    }
+    validate_probe("health_check.probe", &health_check.probe, report);
+}
+
+fn validate_retry(config: &Config, report: &mut ValidationReport) {
+    let retry = &config.retry;
+    if retry.max_attempts == 0 {
+        report
+            .error("retry.max_attempts", "must be at least 1")
+            .hint("1 sends each request once, without retries");
+    }
+    if retry.initial_interval_ms > retry.max_interval_ms {
+        report
+            .error(
+                "retry.initial_interval_ms",
+                format!(
+                    "is longer than retry.max_interval_ms ({})",
+                    retry.max_interval_ms
+                ),
+            )
+            .hint("raise max_interval_ms or lower initial_interval_ms");
+    }
+    if retry.budget_ratio < 0.0 {
+        report
+            .error("retry.budget_ratio", "must not be negative")
+            .hint("0 leaves only the budget_reserve for retries");
+    }
+}
+
+fn validate_priority(config: &Config, report: &mut ValidationReport) {
+    let classes = &config.priority.classes;
+    let policies: [(&str, &ClassPolicy); 3] = [
+        ("interactive", &classes.interactive),
+        ("normal", &classes.normal),
+        ("batch", &classes.batch),
+    ];
+    for (class, policy) in policies {
+        let path = format!("priority.classes.{}", class);
+        if policy.shed_threshold < 0.0 {
+            report
+                .error(format!("{}.shed_threshold", path), "must not be negative")
+                .hint("saturation runs from 0.0 to 1.0");
+        } else if policy.shed_threshold >= 1.0 && policy.max_queue_ms > 0 {
+            report
+                .warning(
+                    format!("{}.max_queue_ms", path),
+                    "has no effect: a shed_threshold of 1.0 or more never holds requests back",
+                )
+                .hint("lower shed_threshold below 1.0 or remove max_queue_ms");
+        }
+    }
+}
+
+fn validate_resilience(config: &Config, report: &mut ValidationReport) {
+    let outlier = &config.outlier_detection;
+    if outlier.enabled {
+        if !(outlier.error_rate_threshold > 0.0 && outlier.error_rate_threshold <= 1.0) {
+            report
+                .error(
+                    "outlier_detection.error_rate_threshold",
+                    "must be above 0.0 and at most 1.0",
+                )
+                .hint("it is the fraction of failed requests, e.g. 0.5");
+        }
+        if outlier.max_ejection_percent > 100 {
+            report
+                .error(
+                    "outlier_detection.max_ejection_percent",
+                    "must be at most 100",
+                )
+                .hint("it is the share of endpoints that may be ejected at once");
+        }
+        if outlier.base_ejection_seconds > outlier.max_ejection_seconds {
+            report
+                .warning(
+                    "outlier_detection.base_ejection_seconds",
+                    format!(
+                        "is cut to max_ejection_seconds ({})",
+                        outlier.max_ejection_seconds
+                    ),
+                )
+                .hint("raise max_ejection_seconds or lower base_ejection_seconds");
+        }
+    }
+
+    let circuit = &config.circuit_breaker;
+    if circuit.enabled {
+        for (field, value) in [
+            ("failure_threshold", circuit.failure_threshold),
+            ("half_open_max_requests", circuit.half_open_max_requests),
+            ("success_threshold", circuit.success_threshold),
+        ] {
+            if value == 0 {
+                report
+                    .error(format!("circuit_breaker.{}", field), "must be at least 1")
+                    .hint("set circuit_breaker.enabled to false to turn the breaker off");
+            }
+        }
+    }
+
+    if config.stream_failover.enabled && config.stream_failover.max_failovers == 0 {
+        report
+            .warning(
+                "stream_failover.enabled",
+                "has no effect while max_failovers is 0",
+            )
+            .hint("raise max_failovers or disable stream failover");
+    }
+
+    let slow_start = &config.slow_start;
+    if !(0.0..=1.0).contains(&slow_start.initial_weight) {
+        report
+            .error("slow_start.initial_weight", "must be between 0.0 and 1.0")
+            .hint("it is the share of its weight a recovered endpoint starts from");
+    } else if slow_start.window_seconds == 0
+        && slow_start.initial_weight != SlowStartConfig::default().initial_weight
+    {
+        report
+            .warning(
+                "slow_start.initial_weight",
+                "has no effect while window_seconds is 0",
+            )
+            .hint("set window_seconds to enable slow start");
+    }
+}
+
+fn validate_canary(config: &Config, report: &mut ValidationReport) {
+    let canary = &config.canary;
+    if !canary.enabled {
+        if !canary.checks.is_empty() {
+            report
+                .warning(
+                    "canary.checks",
+                    "have no effect while canary.enabled is false",
+                )
+                .hint("set canary.enabled to true to run them");
+        }
+        return;
+    }
+
+    if canary.checks.is_empty() {
+        report
+            .warning("canary.enabled", "has no effect without canary.checks")
+            .hint("add a check or disable the canary");
+    }
+    if canary.interval_seconds == 0 {
+        report
+            .error("canary.interval_seconds", "must be at least 1")
+            .hint("canary checks load the GPUs; a few minutes is usual");
+    }
+    for (i, check) in canary.checks.iter().enumerate() {
+        let path = format!("canary.checks[{}]", i);
+        match &check.expect {
+            CanaryExpectation::Regex(pattern) => {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    report
+                        .error(format!("{}.regex", path), format!("invalid regex: {}", e))
+                        .hint("patterns use the syntax of the regex crate");
+                }
+            }
+            CanaryExpectation::Embedding {
+                reference_file,
+                reference,
+                min_similarity,
+            } => {
+                let path = format!("{}.embedding", path);
+                match (reference_file, reference) {
+                    (Some(_), Some(_)) => {
+                        report
+                            .error(
+                                format!("{}.reference", path),
+                                "cannot be combined with reference_file",
+                            )
+                            .hint("keep the reference in one place");
+                    }
+                    (None, None) => {
+                        report
+                            .error(format!("{}.reference_file", path), "is required")
+                            .hint(
+                                "save the embedding of a known-good endpoint (its /api/embed \
+                                 response) as a JSON array, or set reference inline",
+                            );
+                    }
+                    (Some(file), None) if !Path::new(file).exists() => {
+                        report
+                            .error(
+                                format!("{}.reference_file", path),
+                                format!("{} does not exist", file),
+                            )
+                            .hint(
+                                "save the embedding of a known-good endpoint there as a JSON array",
+                            );
+                    }
+                    (None, Some(reference)) if reference.is_empty() => {
+                        report
+                            .error(format!("{}.reference", path), "must not be empty")
+                            .hint("use the embedding of a known-good endpoint");
+                    }
+                    _ => {}
+                }
+                if !(-1.0..=1.0).contains(min_similarity) {
+                    report
+                        .error(
+                            format!("{}.min_similarity", path),
+                            "must be between -1.0 and 1.0",
+                        )
+                        .hint("it is a cosine similarity; 0.99 is usual");
+                }
+            }
+            CanaryExpectation::Text(_) => {}
+        }
+    }
+}
+
+fn validate_discovery(config: &Config, report: &mut ValidationReport) {
+    for (i, file) in config.discovery.files.iter().enumerate() {
+        let path = format!("discovery.files[{}]", i);
+        if file.path.as_os_str().is_empty() {
+            report
+                .error(format!("{}.path", path), "is required")
+                .hint("name a JSON or YAML file, or a directory of them");
+        } else if !file.path.exists() {
+            report
+                .warning(
+                    format!("{}.path", path),
+                    format!("{} does not exist yet", file.path.display()),
+                )
+                .hint("its endpoints are picked up once it is created");
+        }
+        if file.poll_interval_seconds == 0 {
+            report
+                .error(
+                    format!("{}.poll_interval_seconds", path),
+                    "must be at least 1",
+                )
+                .hint("the file is re-read this often");
+        }
+    }
+}
+
+fn validate_registration(config: &Config, report: &mut ValidationReport) {
+    if config.registration.lease_seconds == 0 {
+        report
+            .error("registration.lease_seconds", "must be at least 1")
+            .hint("nodes must send a heartbeat within this many seconds");
+    }
+    let registration = &config.registration;
+    let has_token = registration
+        .token
+        .as_deref()
+        .is_some_and(|token| !token.trim().is_empty());
+    if registration.enabled && !has_token {
+        report
+            .error("registration.token", "is required when registration is enabled")
+            .hint("nodes send it as Authorization: Bearer <token>; OLLAMA_MANAGER_REGISTRATION__TOKEN keeps it out of the file");
+    }
+    if let Some(listen) = registration.listen {
+        if !registration.enabled {
+            report
+                .warning(
+                    "registration.listen",
+                    "has no effect: registration is disabled",
+                )
+                .hint("set registration.enabled to true to serve it");
+        } else if listen == config.server.listen || listen == config.server.admin_listen {
+            report
+                .error(
+                    "registration.listen",
+                    "must differ from server.listen and server.admin_listen",
+                )
+                .hint("give the registration API its own port, e.g. 0.0.0.0:3002");
+        }
+    }
+}
+
+fn validate_misc(config: &Config, report: &mut ValidationReport) {
+    let overrides = &config.timeouts.overrides;
+    for (i, rule) in overrides.iter().enumerate() {
+        let path = format!("timeouts.overrides[{}]", i);
+        if rule.connect_ms.is_none()
+            && rule.first_byte_ms.is_none()
+            && rule.idle_ms.is_none()
+            && rule.total_ms.is_none()
+        {
+            report
+                .warning(path.clone(), "has no effect: it sets no timeout")
+                .hint("set connect_ms, first_byte_ms, idle_ms or total_ms");
+        }
+        if rule.path.is_none() && rule.model.is_none() && i + 1 < overrides.len() {
+            report
+                .warning(
+                    path,
+                    "matches every request, so the overrides after it never apply",
+                )
+                .hint("add a path or model, or move it to the end");
+        }
+    }
+
+    if config.events.stream_buffer == 0 {
+        report
+            .warning("events.stream_buffer", "0 is raised to 1")
+            .hint("a larger buffer lets slow subscribers catch up");
+    }
+
+    if config.reload.poll_interval_seconds == 0 {
+        report
+            .error("reload.poll_interval_seconds", "must be at least 1")
+            .hint("set reload.watch to false to stop polling");
+    } else if !config.reload.watch
+        && config.reload.poll_interval_seconds != ReloadConfig::default().poll_interval_seconds
+    {
+        report
+            .warning(
+                "reload.poll_interval_seconds",
+                "has no effect while reload.watch is false",
+            )
+            .hint("SIGHUP still reloads the config");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(yaml: &str) -> ValidationReport {
+        let yaml = format!("required_model: llama3\n{}", yaml);
+        validate(&serde_yaml::from_str(&yaml).unwrap())
+    }
+
+    fn paths<'a>(issues: impl Iterator<Item = &'a ConfigIssue>) -> Vec<&'a str> {
+        issues.map(|issue| issue.path.as_str()).collect()
+    }
+
+    #[test]
+    fn minimal_config_is_valid() {
+        let report = report("endpoints:\n  - url: http://gpu1:11434\n");
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert!(report.into_result().unwrap().is_empty());
+    }
+
+    #[test]
+    fn errors_are_located_by_path() {
+        let report = report(
+            r#"
+endpoints:
+  - url: ftp://gpu1
+  - url: http://gpu2:11434
+    dns: { host: ollama.internal }
+health_check:
+  healthy_threshold: 0
+strategy: fastest
+retry:
+  initial_interval_ms: 2000
+  max_interval_ms: 1000
+"#,
+        );
+        assert_eq!(
+            paths(report.errors()),
+            [
+                "endpoints[0].url",
+                "endpoints[1].url",
+                "health_check.healthy_threshold",
+                "strategy",
+                "retry.initial_interval_ms",
+            ]
+        );
+        assert!(report
+            .to_string()
+            .contains("(hint: use http:// or https://)"));
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn settings_without_effect_are_warnings() {
+        let report = report(
+            r#"
+endpoints:
+  - url: http://gpu1:11434
+server:
+  admin_listen: "0.0.0.0:3001"
+timeouts:
+  overrides:
+    - total_ms: 1000
+    - path: /api/chat
+"#,
+        );
+        assert!(!report.has_errors(), "{}", report);
+        assert_eq!(
+            paths(report.warnings()),
+            [
+                "server.admin_listen",
+                "timeouts.overrides[0]",
+                "timeouts.overrides[1]",
+            ]
+        );
+    }
+
+    #[test]
+    fn admin_api_needs_its_own_address() {
+        let report = report(
+            r#"
+endpoints:
+  - url: http://gpu1:11434
+server:
+  listen: "127.0.0.1:3001"
+  admin_listen: "127.0.0.1:3001"
+"#,
+        );
+        assert_eq!(paths(report.errors()), ["server.admin_listen"]);
+    }
+
+    #[test]
+    fn endpoints_are_required_without_discovery() {
+        assert_eq!(paths(report("").errors()), ["endpoints"]);
+        assert!(!report("registration:\n  enabled: true\n  token: s3cret\n").has_errors());
+        assert_eq!(
+            paths(report("registration:\n  enabled: true\n").errors()),
+            ["registration.token"]
+        );
+    }
+
+    #[test]
+    fn composite_probes_need_probes() {
+        let report = report(
+            r#"
+endpoints:
+  - url: http://gpu1:11434
+    health_check:
+      probe:
+        type: all
+        probes:
+          - type: tcp
+          - type: any
+            probes: []
+health_check:
+  probe:
+    type: all
+    probes: []
+"#,
+        );
+        assert_eq!(
+            paths(report.errors()),
+            [
+                "endpoints[0].health_check.probe.probes[1].probes",
+                "health_check.probe.probes",
+            ]
+        );
+        let issues: Vec<_> = report.errors().map(ToString::to_string).collect();
+        assert!(
+            issues[0].contains("the probe always fails"),
+            "{}",
+            issues[0]
+        );
+        assert!(
+            issues[1].contains("the probe always passes"),
+            "{}",
+            issues[1]
+        );
+        assert!(issues[1].contains("hint: list at least one probe"));
+    }
+}
@@ -0,0 +1,267 @@
+mod common;
+
+use axum::{routing::get, routing::post, Json, Router};
+use ollama_manager::{admin, server::AppState, LoadBalancer};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const MODEL: &str = "test-model";
+
+/// An Ollama stand-in with `MODEL` installed, whose pulls report two steps.
+async fn start_upstream() -> SocketAddr {
+    let app = Router::new()
+        .route("/", get(|| async { "Ollama is running" }))
+        .route(
+            "/api/tags",
+            get(|| async { Json(json!({ "models": [{ "name": MODEL, "model": MODEL }] })) }),
+        )
+        .route(
+            "/api/pull",
+            post(|| async {
+                "{\"status\":\"pulling manifest\"}\n\
+                 {\"status\":\"downloading\",\"total\":10,\"completed\":10}\n\
+                 {\"status\":\"success\"}\n"
+            }),
+        );
+    common::serve(app).await
+}
+
+/// The admin API over `http://gpu1:11434` and a working upstream.
+async fn start_admin() -> (String, String, Arc<LoadBalancer>) {
+    let upstream = format!("http://{}", start_upstream().await);
+    let config = common::config(&format!(
+        r#"
+endpoints:
+  - url: "http://gpu1:11434"
+    labels: {{ gpu: a100 }}
+  - url: "{upstream}"
+required_model: "{MODEL}"
+"#
+    ));
+    let load_balancer = common::load_balancer(&config);
+    let state = Arc::new(AppState::new(load_balancer.clone(), MODEL.to_string()));
+    let admin = common::serve(admin::router(state)).await;
+    (format!("http://{}", admin), upstream, load_balancer)
+}
+
+async fn send(request: reqwest::RequestBuilder) -> (StatusCode, Value) {
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let body = response.text().await.unwrap();
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+fn urls(load_balancer: &LoadBalancer) -> Vec<String> {
+    load_balancer
+        .endpoints()
+        .iter()
+        .map(|e| e.url.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn endpoints_are_listed_with_their_state() {
+    let (admin, upstream, _load_balancer) = start_admin().await;
+
+    let (status, endpoints) = send(Client::new().get(format!("{}/admin/endpoints", admin))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(endpoints[0]["url"], "http://gpu1:11434");
+    assert_eq!(endpoints[0]["labels"], json!({ "gpu": "a100" }));
+    assert_eq!(endpoints[0]["weight"], 1);
+    assert_eq!(endpoints[0]["draining"], false);
+    assert_eq!(endpoints[1]["url"], upstream.as_str());
+}
+
+#[tokio::test]
+async fn endpoints_can_be_added_updated_and_removed() {
+    let (admin, _upstream, load_balancer) = start_admin().await;
+    let client = Client::new();
+    let endpoints = format!("{}/admin/endpoints", admin);
+
+    let (status, added) = send(
+        client
+            .post(&endpoints)
+            .json(&json!({ "url": "http://gpu3:11434", "weight": 2 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(added["weight"], 2);
+    assert!(urls(&load_balancer).contains(&"http://gpu3:11434".to_string()));
+
+    let (status, _) = send(
+        client
+            .post(&endpoints)
+            .json(&json!({ "url": "http://gpu3:11434/" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(
+        client
+            .post(&endpoints)
+            .json(&json!({ "url": "gpu4:11434" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(
+        client
+            .post(&endpoints)
+            .json(&json!({ "dns": { "host": "ollama.internal" } })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, updated) = send(
+        client
+            .patch(&endpoints)
+            .json(&json!({ "url": "http://gpu3:11434", "max_connections": 4 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["weight"], 2);
+    assert_eq!(updated["max_connections"], 4);
+    let (status, _) = send(
+        client
+            .patch(&endpoints)
+            .json(&json!({ "url": "http://gpu3:11434", "weight": 0 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = send(client.delete(format!("{}?url=http://gpu3:11434", endpoints))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(!urls(&load_balancer).contains(&"http://gpu3:11434".to_string()));
+}
+
+#[tokio::test]
+async fn unknown_endpoints_are_not_found() {
+    let (admin, _upstream, _load_balancer) = start_admin().await;
+    let client = Client::new();
+    let unknown = json!({ "url": "http://gpu9:11434" });
+
+    for request in [
+        client
+            .patch(format!("{}/admin/endpoints", admin))
+            .json(&json!({ "url": "http://gpu9:11434", "weight": 2 })),
+        client.delete(format!("{}/admin/endpoints?url=http://gpu9:11434", admin)),
+        client
+            .post(format!("{}/admin/endpoints/drain", admin))
+            .json(&unknown),
+        client
+            .post(format!("{}/admin/endpoints/undrain", admin))
+            .json(&unknown),
+        client
+            .post(format!("{}/admin/endpoints/check", admin))
+            .json(&unknown),
+        client
+            .post(format!("{}/admin/endpoints/pull", admin))
+            .json(&unknown),
+        client.get(format!(
+            "{}/admin/endpoints/history?url=http://gpu9:11434",
+            admin
+        )),
+    ] {
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"].as_str().unwrap().contains("gpu9"), "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn endpoints_drain_and_undrain() {
+    let (admin, _upstream, load_balancer) = start_admin().await;
+    let client = Client::new();
+    let gpu1 = json!({ "url": "http://gpu1:11434" });
+
+    let (status, drained) = send(
+        client
+            .post(format!("{}/admin/endpoints/drain", admin))
+            .json(&gpu1),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        drained,
+        json!({ "url": "http://gpu1:11434", "draining": true, "current_connections": 0, "drained": true })
+    );
+    assert!(load_balancer
+        .find_endpoint("http://gpu1:11434")
+        .unwrap()
+        .is_draining());
+
+    let (_, status) = send(client.get(format!(
+        "{}/admin/endpoints/drain?url=http://gpu1:11434",
+        admin
+    )))
+    .await;
+    assert_eq!(status["draining"], true);
+
+    let (_, undrained) = send(
+        client
+            .post(format!("{}/admin/endpoints/undrain", admin))
+            .json(&gpu1),
+    )
+    .await;
+    assert_eq!(undrained["draining"], false);
+    assert_eq!(undrained["drained"], false);
+}
+
+#[tokio::test]
+async fn endpoints_are_checked_on_demand() {
+    let (admin, upstream, _load_balancer) = start_admin().await;
+    let client = Client::new();
+
+    let (status, checked) = send(
+        client
+            .post(format!("{}/admin/endpoints/check", admin))
+            .json(&json!({ "url": upstream })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(checked["healthy"], true);
+    assert_eq!(checked["error"], Value::Null);
+}
+
+#[tokio::test]
+async fn models_and_pulls_are_reported_per_endpoint() {
+    let (admin, upstream, _load_balancer) = start_admin().await;
+    let client = Client::new();
+
+    let (_, models) = send(client.get(format!("{}/admin/models", admin))).await;
+    assert_eq!(models[0]["url"], "http://gpu1:11434");
+    assert!(models[0]["error"].is_string());
+    assert_eq!(models[1]["models"], json!([MODEL]));
+
+    let (status, started) = send(
+        client
+            .post(format!("{}/admin/endpoints/pull", admin))
+            .json(&json!({ "url": upstream })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(started["model"], MODEL);
+
+    let mut pulls = Value::Null;
+    for _ in 0..50 {
+        pulls = send(client.get(format!("{}/admin/endpoints/pulls", admin)))
+            .await
+            .1;
+        if pulls[0]["done"] == true {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(pulls[0]["done"], true, "{}", pulls);
+    assert_eq!(pulls[0]["status"], "success");
+    assert_eq!(pulls[0]["error"], Value::Null);
+}
+
+#[tokio::test]
+async fn registry_routes_need_registration_enabled() {
+    let (admin, _upstream, _load_balancer) = start_admin().await;
+    let (status, _) =
+        send(Client::new().get(format!("{}/admin/endpoints/registered", admin))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
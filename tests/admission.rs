@@ -0,0 +1,87 @@
+mod common;
+
+use ollama_manager::{priority::Priority, LoadBalancer, LoadBalancerError};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn load_balancer() -> Arc<LoadBalancer> {
+    let config = common::config(
+        r#"
+endpoints:
+  - url: "http://gpu1:11434"
+    max_connections: 4
+priority:
+  classes:
+    normal:
+      shed_threshold: 0.9
+      max_queue_ms: 0
+    batch:
+      shed_threshold: 0.5
+      max_queue_ms: 1000
+required_model: "test-model"
+"#,
+    );
+    let load_balancer = common::load_balancer(&config);
+    common::mark_healthy(&load_balancer);
+    load_balancer
+}
+
+async fn admitted(load_balancer: &LoadBalancer, priority: Priority) -> bool {
+    match load_balancer.admit(priority).await {
+        Ok(()) => true,
+        Err(LoadBalancerError::LoadShed(shed)) => {
+            assert_eq!(shed, priority);
+            false
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+#[tokio::test]
+async fn lower_classes_are_shed_first() {
+    let load_balancer = load_balancer();
+    let endpoint = load_balancer.find_endpoint("http://gpu1:11434").unwrap();
+    let mut connections: Vec<_> = (0..3).map(|_| endpoint.track_connection()).collect();
+    assert_eq!(load_balancer.saturation(), 0.75);
+    assert!(admitted(&load_balancer, Priority::Interactive).await);
+    assert!(admitted(&load_balancer, Priority::Normal).await);
+
+    connections.push(endpoint.track_connection());
+    assert!(admitted(&load_balancer, Priority::Interactive).await);
+    assert!(!admitted(&load_balancer, Priority::Normal).await);
+}
+
+#[tokio::test]
+async fn held_back_requests_wait_for_capacity() {
+    let load_balancer = load_balancer();
+    let endpoint = load_balancer.find_endpoint("http://gpu1:11434").unwrap();
+    let first = endpoint.track_connection();
+    let _second = endpoint.track_connection();
+    let _third = endpoint.track_connection();
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(first);
+    });
+    assert!(admitted(&load_balancer, Priority::Batch).await);
+
+    // Saturation never drops while the queue time runs out
+    let _fourth = endpoint.track_connection();
+    assert!(!admitted(&load_balancer, Priority::Batch).await);
+}
+
+#[tokio::test]
+async fn requests_fail_fast_without_available_endpoints() {
+    let load_balancer = load_balancer();
+    load_balancer
+        .find_endpoint("http://gpu1:11434")
+        .unwrap()
+        .drain();
+
+    let started = std::time::Instant::now();
+    assert!(matches!(
+        load_balancer.admit(Priority::Batch).await,
+        Err(LoadBalancerError::NoHealthyEndpoints)
+    ));
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
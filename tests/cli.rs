@@ -0,0 +1,114 @@
+mod common;
+
+use axum::{routing::get, Json, Router};
+use std::net::SocketAddr;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+const MODEL: &str = "test-model";
+
+/// An Ollama stand-in with `models` installed, answering `/` after `delay`.
+async fn start_upstream(models: &'static [&'static str], delay: Duration) -> SocketAddr {
+    let app = Router::new()
+        .route(
+            "/",
+            get(move || async move {
+                tokio::time::sleep(delay).await;
+                "Ollama is running"
+            }),
+        )
+        .route(
+            "/api/tags",
+            get(move || async move {
+                let models: Vec<_> = models
+                    .iter()
+                    .map(|name| serde_json::json!({ "name": name, "model": name }))
+                    .collect();
+                Json(serde_json::json!({ "models": models }))
+            }),
+        );
+    common::serve(app).await
+}
+
+/// Runs the binary with `args` against a temporary config file.
+async fn run(config: String, args: &[&str]) -> Output {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "ollama-manager-cli-{}-{}.yaml",
+        std::process::id(),
+        RUNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, config).unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ollama-manager"));
+    command.arg("--config").arg(&path).args(args);
+    let output = tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+fn row<'a>(stdout: &'a str, addr: &SocketAddr) -> &'a str {
+    let url = format!("http://{}", addr);
+    stdout
+        .lines()
+        .find(|line| line.starts_with(&url))
+        .unwrap_or_else(|| panic!("no row for {} in\n{}", url, stdout))
+}
+
+#[tokio::test]
+async fn check_probes_endpoints_with_their_own_settings() {
+    let healthy = start_upstream(&[MODEL], Duration::ZERO).await;
+    // Lacks the required model, so only passes with its own TCP probe
+    let tcp_probed = start_upstream(&[], Duration::ZERO).await;
+    let slow = start_upstream(&[MODEL], Duration::from_secs(3)).await;
+
+    let output = run(
+        format!(
+            r#"
+endpoints:
+  - url: "http://{healthy}"
+  - url: "http://{tcp_probed}"
+    health_check:
+      probe:
+        type: tcp
+  - url: "http://{slow}"
+    health_check:
+      timeout_seconds: 1
+required_model: "{MODEL}"
+"#
+        ),
+        &["check"],
+    )
+    .await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(row(&stdout, &healthy).contains(" healthy "));
+    assert!(row(&stdout, &tcp_probed).contains(" healthy "));
+    assert!(row(&stdout, &slow).contains("error: no answer within 1s"));
+    assert!(stdout.contains("2/3 endpoints healthy"), "{}", stdout);
+}
+
+#[tokio::test]
+async fn check_fails_without_healthy_endpoints() {
+    let missing_model = start_upstream(&["other-model"], Duration::ZERO).await;
+
+    let output = run(
+        format!(
+            r#"
+endpoints:
+  - url: "http://{missing_model}"
+required_model: "{MODEL}"
+"#
+        ),
+        &["check"],
+    )
+    .await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(!output.status.success());
+    assert!(row(&stdout, &missing_model).contains("other-model"));
+    assert!(stdout.contains("0/1 endpoints healthy"), "{}", stdout);
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use axum::{body::Body, response::Response, routing::get, routing::post, Json, Router};
+use bytes::Bytes;
+use common::serve;
+use futures_util::StreamExt;
+use ollama_manager::LoadBalancer;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MODEL: &str = "test-model";
+
+/// Flags when the upstream side of a request has started and when it has been
+/// dropped, which hyper only does once the proxy has closed the connection.
+#[derive(Clone, Default)]
+struct Dropped {
+    started: Arc<AtomicBool>,
+    dropped: Arc<AtomicBool>,
+}
+
+struct DropSignal(Dropped);
+
+impl DropSignal {
+    fn new(dropped: &Dropped) -> Self {
+        dropped.started.store(true, Ordering::SeqCst);
+        Self(dropped.clone())
+    }
+}
+
+impl Drop for DropSignal {
+    fn drop(&mut self) {
+        self.0.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+async fn wait_for(flag: &AtomicBool) -> bool {
+    for _ in 0..100 {
+        if flag.load(Ordering::SeqCst) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+impl Dropped {
+    async fn wait_started(&self) -> bool {
+        wait_for(&self.started).await
+    }
+
+    async fn wait(&self) -> bool {
+        wait_for(&self.dropped).await
+    }
+}
+
+/// An Ollama stand-in that streams `/api/generate` forever and never answers `/api/chat`.
+async fn start_upstream(streaming: Dropped, waiting: Dropped) -> SocketAddr {
+    let app = Router::new()
+        .route(
+            "/api/tags",
+            get(|| async {
+                Json(serde_json::json!({ "models": [{ "name": MODEL, "model": MODEL }] }))
+            }),
+        )
+        .route(
+            "/api/generate",
+            post(move || {
+                let signal = DropSignal::new(&streaming);
+                async move {
+                    let chunks = futures_util::stream::unfold(signal, |signal| async move {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        let chunk =
+                            Bytes::from_static(b"{\"response\":\"token\",\"done\":false}\n");
+                        Some((Ok::<_, std::io::Error>(chunk), signal))
+                    });
+                    Response::builder()
+                        .header("content-type", "application/x-ndjson")
+                        .body(Body::from_stream(chunks))
+                        .unwrap()
+                }
+            }),
+        )
+        .route(
+            "/api/chat",
+            post(move || {
+                let signal = DropSignal::new(&waiting);
+                async move {
+                    let _signal = signal;
+                    std::future::pending::<()>().await;
+                    ""
+                }
+            }),
+        );
+    serve(app).await
+}
+
+async fn start_proxy(upstream: SocketAddr) -> (SocketAddr, Arc<LoadBalancer>) {
+    let config = common::config(&format!(
+        r#"
+endpoints:
+  - url: "http://{upstream}"
+    weight: 1
+    max_connections: 10
+health_check:
+  interval_seconds: 60
+  timeout_seconds: 2
+  unhealthy_threshold: 3
+  healthy_threshold: 2
+strategy: "round_robin"
+retry:
+  max_attempts: 1
+  initial_interval_ms: 10
+  max_interval_ms: 10
+required_model: "{MODEL}"
+"#
+    ));
+    common::start_proxy(&config).await
+}
+
+#[tokio::test]
+async fn disconnect_mid_stream_closes_upstream() {
+    let streaming = Dropped::default();
+    let upstream = start_upstream(streaming.clone(), Dropped::default()).await;
+    let (proxy, load_balancer) = start_proxy(upstream).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/api/generate", proxy))
+        .json(&serde_json::json!({ "model": MODEL, "prompt": "hi" }))
+        .send()
+        .await
+        .unwrap();
+    let mut body = response.bytes_stream();
+    body.next().await.unwrap().unwrap();
+    assert_eq!(load_balancer.endpoints()[0].get_connections(), 1);
+
+    drop(body);
+
+    assert!(streaming.wait().await, "upstream stream was never dropped");
+    assert_eq!(load_balancer.endpoints()[0].get_connections(), 0);
+}
+
+#[tokio::test]
+async fn disconnect_before_response_closes_upstream() {
+    let waiting = Dropped::default();
+    let upstream = start_upstream(Dropped::default(), waiting.clone()).await;
+    let (proxy, load_balancer) = start_proxy(upstream).await;
+
+    let request = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("http://{}/api/chat", proxy))
+            .json(&serde_json::json!({ "model": MODEL, "messages": [] }))
+            .send(),
+    );
+    assert!(
+        waiting.wait_started().await,
+        "upstream never got the request"
+    );
+    request.abort();
+
+    assert!(waiting.wait().await, "upstream request was never dropped");
+    assert_eq!(load_balancer.endpoints()[0].get_connections(), 0);
+}
@@ -0,0 +1,65 @@
+//! Setup shared by the integration tests. Each test binary uses a different
+//! part of it.
+#![allow(dead_code)]
+
+use axum::Router;
+use ollama_manager::{
+    health::{HealthCheck, HealthChecker},
+    lb::RoundRobin,
+    server::{self, AppState},
+    Config, Endpoint, LoadBalancer,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+pub struct AlwaysHealthy;
+
+#[async_trait::async_trait]
+impl HealthCheck for AlwaysHealthy {
+    async fn check_health(&self, _endpoint: &Endpoint) -> ollama_manager::Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Parses a YAML config, failing the test if it does not validate.
+pub fn config(yaml: &str) -> Config {
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert!(!config.validate().has_errors());
+    config
+}
+
+/// A round robin load balancer whose health probes always pass.
+pub fn load_balancer(config: &Config) -> Arc<LoadBalancer> {
+    let health_checker = HealthChecker::new(Box::new(AlwaysHealthy), config.health_check.clone());
+    Arc::new(LoadBalancer::new(
+        config.clone(),
+        Box::new(RoundRobin::new()),
+        health_checker,
+    ))
+}
+
+/// Marks every current endpoint healthy without waiting for a probe.
+pub fn mark_healthy(load_balancer: &LoadBalancer) {
+    for endpoint in load_balancer.endpoints().iter() {
+        endpoint.record_health(true, "test setup");
+    }
+}
+
+pub async fn serve(app: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    addr
+}
+
+/// Serves the proxy for `config` with every endpoint already healthy.
+pub async fn start_proxy(config: &Config) -> (SocketAddr, Arc<LoadBalancer>) {
+    let load_balancer = load_balancer(config);
+    mark_healthy(&load_balancer);
+    let state = Arc::new(AppState::new(
+        load_balancer.clone(),
+        config.required_model.clone(),
+    ));
+    (serve(server::router(state)).await, load_balancer)
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use axum::{routing::get, routing::post, Json, Router};
+use ollama_manager::{admin, server::AppState, LoadBalancer};
+use std::process::{Command, Output};
+use std::sync::Arc;
+
+const MODEL: &str = "test-model";
+
+/// An Ollama stand-in whose pulls finish at once.
+async fn start_upstream() -> String {
+    let app = Router::new()
+        .route("/", get(|| async { "Ollama is running" }))
+        .route(
+            "/api/tags",
+            get(|| async {
+                Json(serde_json::json!({ "models": [{ "name": MODEL, "model": MODEL }] }))
+            }),
+        )
+        .route(
+            "/api/pull",
+            post(|| async { "{\"status\":\"pulling manifest\"}\n{\"status\":\"success\"}\n" }),
+        );
+    format!("http://{}", common::serve(app).await)
+}
+
+/// The admin API of a manager with one working endpoint.
+async fn start_admin() -> (String, String, Arc<LoadBalancer>) {
+    let upstream = start_upstream().await;
+    let config = common::config(&format!(
+        r#"
+endpoints:
+  - url: "{upstream}"
+required_model: "{MODEL}"
+"#
+    ));
+    let load_balancer = common::load_balancer(&config);
+    let state = Arc::new(AppState::new(load_balancer.clone(), MODEL.to_string()));
+    let admin = common::serve(admin::router(state)).await;
+    (format!("http://{}", admin), upstream, load_balancer)
+}
+
+/// Runs `ollama-manager ctl --url <admin> <args>`.
+async fn ctl(admin: &str, args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ollama-manager"));
+    command.args(["ctl", "--url", admin]).args(args);
+    tokio::task::spawn_blocking(move || command.output().unwrap())
+        .await
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[tokio::test]
+async fn endpoints_are_added_drained_and_removed() {
+    let (admin, upstream, load_balancer) = start_admin().await;
+
+    let added = ctl(
+        &admin,
+        &[
+            "add",
+            "http://gpu2:11434",
+            "--weight",
+            "3",
+            "--label",
+            "gpu=a100",
+        ],
+    )
+    .await;
+    assert_eq!(
+        stdout(&added),
+        "Added http://gpu2:11434 (weight 3, max_connections 100)\n"
+    );
+    let gpu2 = load_balancer.find_endpoint("http://gpu2:11434").unwrap();
+    assert_eq!(gpu2.labels.get("gpu").map(String::as_str), Some("a100"));
+
+    let table = stdout(&ctl(&admin, &["endpoints"]).await);
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines[0].starts_with("ENDPOINT"), "{}", table);
+    assert!(lines[1].starts_with(&upstream), "{}", table);
+    assert!(lines[2].starts_with("http://gpu2:11434"), "{}", table);
+    assert!(lines[2].contains("gpu=a100"), "{}", table);
+
+    let drained = ctl(&admin, &["drain", "http://gpu2:11434"]).await;
+    assert_eq!(stdout(&drained), "http://gpu2:11434 is drained\n");
+    let table = stdout(&ctl(&admin, &["endpoints"]).await);
+    assert!(
+        table.lines().nth(2).unwrap().ends_with("draining"),
+        "{}",
+        table
+    );
+    let undrained = ctl(&admin, &["undrain", "http://gpu2:11434"]).await;
+    assert_eq!(
+        stdout(&undrained),
+        "http://gpu2:11434 is back in rotation\n"
+    );
+
+    let removed = ctl(&admin, &["remove", "http://gpu2:11434"]).await;
+    assert_eq!(stdout(&removed), "Removed http://gpu2:11434\n");
+    assert!(load_balancer.find_endpoint("http://gpu2:11434").is_err());
+}
+
+#[tokio::test]
+async fn json_output_is_the_admin_api_response() {
+    let (admin, upstream, _load_balancer) = start_admin().await;
+
+    let output = ctl(&admin, &["--json", "endpoints"]).await;
+    let endpoints: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(endpoints[0]["url"], upstream.as_str());
+
+    let output = ctl(&admin, &["models", "--json"]).await;
+    let models: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(models[0]["models"], serde_json::json!([MODEL]));
+}
+
+#[tokio::test]
+async fn pulls_are_followed_to_the_end() {
+    let (admin, upstream, _load_balancer) = start_admin().await;
+
+    let output = ctl(&admin, &["pull", &upstream]).await;
+    assert_eq!(
+        stdout(&output),
+        format!("Pulled {} on {}\n", MODEL, upstream)
+    );
+    let pulls = stdout(&ctl(&admin, &["pulls"]).await);
+    let row = pulls.lines().nth(1).unwrap();
+    assert!(row.starts_with(&upstream), "{}", pulls);
+    assert!(row.contains(" done "), "{}", pulls);
+}
+
+#[tokio::test]
+async fn admin_api_errors_fail_the_command() {
+    let (admin, _upstream, _load_balancer) = start_admin().await;
+
+    let output = ctl(&admin, &["drain", "http://gpu9:11434"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("404 Not Found: Unknown endpoint: http://gpu9:11434"),
+        "{}",
+        stderr
+    );
+
+    let output = ctl("http://127.0.0.1:1", &["endpoints"]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("could not reach the admin API"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use ollama_manager::{
+    discovery::Discovery,
+    dns::{self, DnsAnswer, DnsDiscovery, Resolver, SrvTarget},
+    LoadBalancer, LoadBalancerError,
+};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Answers from in-memory records; a name without records fails the lookup.
+#[derive(Default)]
+struct StubResolver {
+    hosts: Mutex<HashMap<String, DnsAnswer<IpAddr>>>,
+    srv: Mutex<HashMap<String, DnsAnswer<SrvTarget>>>,
+}
+
+impl StubResolver {
+    fn set_host(&self, name: &str, ips: &[&str], ttl_seconds: u64) {
+        self.hosts.lock().unwrap().insert(
+            name.to_string(),
+            DnsAnswer {
+                records: ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+                ttl: Duration::from_secs(ttl_seconds),
+            },
+        );
+    }
+
+    fn set_srv(&self, name: &str, targets: &[(&str, u16)], ttl_seconds: u64) {
+        self.srv.lock().unwrap().insert(
+            name.to_string(),
+            DnsAnswer {
+                records: targets
+                    .iter()
+                    .map(|(host, port)| SrvTarget {
+                        host: host.to_string(),
+                        port: *port,
+                    })
+                    .collect(),
+                ttl: Duration::from_secs(ttl_seconds),
+            },
+        );
+    }
+
+    fn remove_host(&self, name: &str) {
+        self.hosts.lock().unwrap().remove(name);
+    }
+}
+
+#[async_trait::async_trait]
+impl Resolver for StubResolver {
+    async fn lookup_ip(&self, host: &str) -> ollama_manager::Result<DnsAnswer<IpAddr>> {
+        self.hosts
+            .lock()
+            .unwrap()
+            .get(host)
+            .cloned()
+            .ok_or_else(|| LoadBalancerError::DnsError(format!("no server for {}", host)))
+    }
+
+    async fn lookup_srv(&self, name: &str) -> ollama_manager::Result<DnsAnswer<SrvTarget>> {
+        self.srv
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| LoadBalancerError::DnsError(format!("no server for {}", name)))
+    }
+}
+
+fn setup(resolver: Arc<StubResolver>) -> (Arc<LoadBalancer>, Vec<DnsDiscovery>) {
+    let config = common::config(
+        r#"
+endpoints:
+  - url: "http://static:11434"
+    weight: 1
+    max_connections: 10
+  - dns:
+      host: "gpu.internal"
+      min_refresh_seconds: 5
+      max_refresh_seconds: 300
+    weight: 2
+    max_connections: 4
+  - dns:
+      srv: "_ollama._tcp.internal"
+required_model: "test-model"
+"#,
+    );
+    (
+        common::load_balancer(&config),
+        dns::discoveries(&config, resolver),
+    )
+}
+
+fn urls(load_balancer: &LoadBalancer) -> Vec<String> {
+    let mut urls: Vec<String> = load_balancer
+        .endpoints()
+        .iter()
+        .map(|e| e.url.clone())
+        .collect();
+    urls.sort();
+    urls
+}
+
+#[tokio::test]
+async fn host_records_follow_dns_and_keep_state() {
+    let resolver = Arc::new(StubResolver::default());
+    resolver.set_host("gpu.internal", &["10.0.0.1", "10.0.0.2"], 30);
+    let (load_balancer, discoveries) = setup(resolver.clone());
+    let host = &discoveries[0];
+
+    assert_eq!(host.refresh(&load_balancer).await, Duration::from_secs(30));
+    assert_eq!(
+        urls(&load_balancer),
+        [
+            "http://10.0.0.1:11434",
+            "http://10.0.0.2:11434",
+            "http://static:11434"
+        ]
+    );
+    let first = load_balancer
+        .find_endpoint("http://10.0.0.1:11434")
+        .unwrap();
+    assert_eq!((first.weight, first.max_connections), (2, 4));
+    let _connection = first.track_connection();
+
+    resolver.set_host("gpu.internal", &["10.0.0.1", "fd00::3"], 30);
+    host.refresh(&load_balancer).await;
+    assert_eq!(
+        urls(&load_balancer),
+        [
+            "http://10.0.0.1:11434",
+            "http://[fd00::3]:11434",
+            "http://static:11434"
+        ]
+    );
+    let first = load_balancer
+        .find_endpoint("http://10.0.0.1:11434")
+        .unwrap();
+    assert_eq!(first.get_connections(), 1);
+}
+
+#[tokio::test]
+async fn refresh_follows_ttl_within_bounds() {
+    let resolver = Arc::new(StubResolver::default());
+    let (load_balancer, discoveries) = setup(resolver.clone());
+    let host = &discoveries[0];
+
+    resolver.set_host("gpu.internal", &["10.0.0.1"], 1);
+    assert_eq!(host.refresh(&load_balancer).await, Duration::from_secs(5));
+    resolver.set_host("gpu.internal", &["10.0.0.1"], 86400);
+    assert_eq!(host.refresh(&load_balancer).await, Duration::from_secs(300));
+}
+
+#[tokio::test]
+async fn failed_lookup_keeps_last_known_endpoints() {
+    let resolver = Arc::new(StubResolver::default());
+    resolver.set_host("gpu.internal", &["10.0.0.1"], 60);
+    let (load_balancer, discoveries) = setup(resolver.clone());
+    let host = &discoveries[0];
+    host.refresh(&load_balancer).await;
+
+    resolver.remove_host("gpu.internal");
+    assert_eq!(host.refresh(&load_balancer).await, Duration::from_secs(5));
+    assert_eq!(
+        urls(&load_balancer),
+        ["http://10.0.0.1:11434", "http://static:11434"]
+    );
+
+    resolver.set_host("gpu.internal", &[], 60);
+    host.refresh(&load_balancer).await;
+    assert_eq!(urls(&load_balancer), ["http://static:11434"]);
+}
+
+#[tokio::test]
+async fn srv_targets_become_endpoints() {
+    let resolver = Arc::new(StubResolver::default());
+    resolver.set_srv(
+        "_ollama._tcp.internal",
+        &[("gpu1.internal", 11434), ("gpu2.internal", 11435)],
+        60,
+    );
+    let (load_balancer, discoveries) = setup(resolver.clone());
+    discoveries[1].refresh(&load_balancer).await;
+
+    assert_eq!(
+        urls(&load_balancer),
+        [
+            "http://gpu1.internal:11434",
+            "http://gpu2.internal:11435",
+            "http://static:11434"
+        ]
+    );
+    // A target leaving the SRV record removes its endpoint only
+    resolver.set_srv("_ollama._tcp.internal", &[("gpu1.internal", 11434)], 60);
+    discoveries[1].refresh(&load_balancer).await;
+    assert_eq!(
+        urls(&load_balancer),
+        ["http://gpu1.internal:11434", "http://static:11434"]
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use futures_util::StreamExt;
+use ollama_manager::{admin, server::AppState};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const ENDPOINT: &str = "http://gpu1:11434";
+
+/// The admin API over one endpoint, once its first probe has passed.
+async fn start_admin() -> SocketAddr {
+    let config = common::config(
+        r#"
+endpoints:
+  - url: "http://gpu1:11434"
+required_model: "test-model"
+"#,
+    );
+    let load_balancer = common::load_balancer(&config);
+    // Let the first probe settle so its health change is not streamed
+    while !load_balancer.endpoints()[0].is_healthy() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let state = Arc::new(AppState::new(load_balancer, config.required_model.clone()));
+    common::serve(admin::router(state)).await
+}
+
+async fn post(admin: SocketAddr, path: &str) {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}{}", admin, path))
+        .json(&serde_json::json!({ "url": ENDPOINT }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+}
+
+/// Reads Server-Sent Events until `count` have arrived, as (name, data) pairs.
+async fn read_events(response: reqwest::Response, count: usize) -> Vec<(String, String)> {
+    let mut body = response.bytes_stream();
+    let mut text = String::new();
+    let mut events = Vec::new();
+    while events.len() < count {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+            .await
+            .expect("no event within 5s")
+            .unwrap()
+            .unwrap();
+        text.push_str(std::str::from_utf8(&chunk).unwrap());
+        while let Some(end) = text.find("\n\n") {
+            let block: String = text.drain(..end + 2).collect();
+            let field = |name: &str| {
+                block
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(|value| value.trim().to_string())
+            };
+            if let (Some(name), Some(data)) = (field("event:"), field("data:")) {
+                events.push((name, data));
+            }
+        }
+    }
+    events
+}
+
+#[tokio::test]
+async fn fleet_events_are_streamed() {
+    let admin = start_admin().await;
+    let stream = reqwest::get(format!("http://{}/admin/events", admin))
+        .await
+        .unwrap();
+    assert_eq!(stream.headers()["content-type"], "text/event-stream");
+
+    post(admin, "/admin/endpoints/drain").await;
+    post(admin, "/admin/endpoints/undrain").await;
+
+    let events = read_events(stream, 2).await;
+    let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["draining", "undrained"]);
+    let draining: serde_json::Value = serde_json::from_str(&events[0].1).unwrap();
+    assert_eq!(draining["type"], "draining");
+    assert_eq!(draining["endpoint"], ENDPOINT);
+    assert!(draining["at"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn history_lists_an_endpoints_events() {
+    let admin = start_admin().await;
+    post(admin, "/admin/endpoints/drain").await;
+    post(admin, "/admin/endpoints/undrain").await;
+
+    let history: Vec<serde_json::Value> = reqwest::get(format!(
+        "http://{}/admin/endpoints/history?url={}&limit=1",
+        admin, ENDPOINT
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["type"], "undrained");
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use ollama_manager::{
+    discovery::{Discovery, FileDiscovery, FileDiscoveryConfig},
+    LoadBalancer,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn load_balancer() -> Arc<LoadBalancer> {
+    common::load_balancer(&common::config(
+        r#"
+endpoints:
+  - url: "http://static:11434"
+required_model: "test-model"
+"#,
+    ))
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ollama-manager-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn discovery(path: PathBuf) -> FileDiscovery {
+    FileDiscovery::new(FileDiscoveryConfig {
+        path,
+        poll_interval_seconds: 1,
+    })
+}
+
+fn urls(load_balancer: &LoadBalancer) -> Vec<String> {
+    let mut urls: Vec<String> = load_balancer
+        .endpoints()
+        .iter()
+        .map(|e| e.url.clone())
+        .collect();
+    urls.sort();
+    urls
+}
+
+#[tokio::test]
+async fn file_changes_add_and_remove_endpoints() {
+    let dir = scratch_dir("file");
+    let file = dir.join("hosts.yaml");
+    std::fs::write(
+        &file,
+        r#"
+endpoints:
+  - url: "http://gpu1:11434"
+    weight: 3
+    labels:
+      gpu: a100
+  - url: "http://gpu2:11434"
+"#,
+    )
+    .unwrap();
+    let load_balancer = load_balancer();
+    let discovery = discovery(file.clone());
+
+    discovery.refresh(&load_balancer).await;
+    assert_eq!(
+        urls(&load_balancer),
+        [
+            "http://gpu1:11434",
+            "http://gpu2:11434",
+            "http://static:11434"
+        ]
+    );
+    let gpu1 = load_balancer.find_endpoint("http://gpu1:11434").unwrap();
+    assert_eq!(gpu1.weight, 3);
+    assert_eq!(gpu1.labels.get("gpu").map(String::as_str), Some("a100"));
+
+    std::fs::write(&file, r#"[{"url": "http://gpu2:11434"}]"#).unwrap();
+    discovery.refresh(&load_balancer).await;
+    assert_eq!(
+        urls(&load_balancer),
+        ["http://gpu2:11434", "http://static:11434"]
+    );
+
+    // A broken file keeps the last known endpoints
+    std::fs::write(&file, "- url: [").unwrap();
+    discovery.refresh(&load_balancer).await;
+    std::fs::write(&file, "- url: ftp://gpu3").unwrap();
+    assert!(discovery.discover().await.is_err());
+    discovery.refresh(&load_balancer).await;
+    assert_eq!(
+        urls(&load_balancer),
+        ["http://gpu2:11434", "http://static:11434"]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn directory_files_are_combined() {
+    let dir = scratch_dir("dir");
+    std::fs::write(dir.join("a.json"), r#"[{"url": "http://gpu1:11434"}]"#).unwrap();
+    std::fs::write(dir.join("b.yml"), "- url: http://gpu2:11434\n").unwrap();
+    std::fs::write(dir.join("notes.txt"), "not endpoints").unwrap();
+    let load_balancer = load_balancer();
+    let discovery = discovery(dir.clone());
+
+    discovery.refresh(&load_balancer).await;
+    assert_eq!(
+        urls(&load_balancer),
+        [
+            "http://gpu1:11434",
+            "http://gpu2:11434",
+            "http://static:11434"
+        ]
+    );
+
+    std::fs::remove_file(dir.join("a.json")).unwrap();
+    discovery.refresh(&load_balancer).await;
+    assert_eq!(
+        urls(&load_balancer),
+        ["http://gpu2:11434", "http://static:11434"]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
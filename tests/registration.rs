@@ -0,0 +1,154 @@
+mod common;
+
+use ollama_manager::{
+    admin,
+    discovery::Discovery,
+    registration::{Registration, RegistrationConfig, Registry},
+    server::AppState,
+    LoadBalancer, LoadBalancerError,
+};
+use reqwest::StatusCode;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn load_balancer() -> Arc<LoadBalancer> {
+    common::load_balancer(&common::config(
+        r#"
+registration:
+  enabled: true
+  token: s3cret
+required_model: "test-model"
+"#,
+    ))
+}
+
+fn registration(url: &str) -> Registration {
+    Registration {
+        url: url.to_string(),
+        weight: 2,
+        max_connections: 8,
+        labels: BTreeMap::from([("gpu".to_string(), "a100".to_string())]),
+    }
+}
+
+#[tokio::test]
+async fn registered_nodes_expire_without_heartbeats() {
+    let load_balancer = load_balancer();
+    let registry = Registry::new(RegistrationConfig {
+        enabled: true,
+        lease_seconds: 1,
+        ..Default::default()
+    });
+
+    registry
+        .register(registration("http://gpu1:11434/"))
+        .unwrap();
+    registry
+        .register(registration("http://gpu2:11434"))
+        .unwrap();
+    registry.refresh(&load_balancer).await;
+    let gpu1 = load_balancer.find_endpoint("http://gpu1:11434").unwrap();
+    assert_eq!((gpu1.weight, gpu1.max_connections), (2, 8));
+    assert_eq!(gpu1.labels.get("gpu").map(String::as_str), Some("a100"));
+    assert_eq!(load_balancer.endpoints().len(), 2);
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    registry.heartbeat("http://gpu1:11434").unwrap();
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    registry.refresh(&load_balancer).await;
+
+    let urls: Vec<String> = load_balancer
+        .endpoints()
+        .iter()
+        .map(|e| e.url.clone())
+        .collect();
+    assert_eq!(urls, ["http://gpu1:11434"]);
+    assert!(matches!(
+        registry.heartbeat("http://gpu2:11434"),
+        Err(LoadBalancerError::NotRegistered(_))
+    ));
+
+    registry.deregister("http://gpu1:11434").unwrap();
+    registry.refresh(&load_balancer).await;
+    assert!(load_balancer.endpoints().is_empty());
+}
+
+#[tokio::test]
+async fn invalid_registrations_are_rejected() {
+    let registry = Registry::new(RegistrationConfig::default());
+    assert!(matches!(
+        registry.register(registration("gpu1:11434")),
+        Err(LoadBalancerError::BadRequest(_))
+    ));
+    let mut zero_weight = registration("http://gpu1:11434");
+    zero_weight.weight = 0;
+    assert!(matches!(
+        registry.register(zero_weight),
+        Err(LoadBalancerError::BadRequest(_))
+    ));
+    assert!(registry.nodes().is_empty());
+}
+
+/// The admin API and the registration-only API, sharing one registry.
+async fn start_apis() -> (SocketAddr, SocketAddr, Arc<LoadBalancer>) {
+    let load_balancer = load_balancer();
+    let registry = Arc::new(Registry::new(RegistrationConfig {
+        enabled: true,
+        token: Some("s3cret".to_string()),
+        ..Default::default()
+    }));
+    let state = Arc::new(
+        AppState::new(load_balancer.clone(), "test-model".to_string()).with_registry(registry),
+    );
+    let admin = common::serve(admin::router(state.clone())).await;
+    let registration = common::serve(admin::registration_router(state)).await;
+    (admin, registration, load_balancer)
+}
+
+async fn register(api: SocketAddr, token: Option<&str>) -> StatusCode {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://{}/admin/endpoints/register", api))
+        .json(&serde_json::json!({ "url": "http://gpu1:11434" }));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap().status()
+}
+
+#[tokio::test]
+async fn registration_needs_the_token() {
+    let (admin, _registration, load_balancer) = start_apis().await;
+
+    assert_eq!(register(admin, None).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        register(admin, Some("guess")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    let heartbeat = reqwest::Client::new()
+        .post(format!("http://{}/admin/endpoints/heartbeat", admin))
+        .json(&serde_json::json!({ "url": "http://gpu1:11434" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(heartbeat.status(), StatusCode::UNAUTHORIZED);
+    assert!(load_balancer.endpoints().is_empty());
+
+    assert_eq!(register(admin, Some("s3cret")).await, StatusCode::OK);
+    assert_eq!(load_balancer.endpoints().len(), 1);
+}
+
+#[tokio::test]
+async fn registration_listener_serves_nothing_else() {
+    let (_admin, registration, load_balancer) = start_apis().await;
+
+    assert_eq!(register(registration, Some("s3cret")).await, StatusCode::OK);
+    assert_eq!(load_balancer.endpoints().len(), 1);
+    for path in ["/admin/endpoints", "/admin/endpoints/registered"] {
+        let response = reqwest::get(format!("http://{}{}", registration, path))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use ollama_manager::{config::ConfigLoader, reload::ConfigReloader, LoadBalancer};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A config file in the temp dir, removed when dropped.
+struct ConfigFile(PathBuf);
+
+impl ConfigFile {
+    fn new(name: &str, contents: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "ollama-manager-reload-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+
+    fn write(&self, contents: &str) {
+        std::fs::write(&self.0, contents).unwrap();
+    }
+}
+
+impl Drop for ConfigFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn start(file: &ConfigFile) -> (ConfigReloader, Arc<LoadBalancer>) {
+    let loader = ConfigLoader::new(Some(file.0.clone()));
+    let config = loader.load().unwrap();
+    let load_balancer = common::load_balancer(&config);
+    (
+        ConfigReloader::new(loader, config, load_balancer.clone()),
+        load_balancer,
+    )
+}
+
+fn urls(load_balancer: &LoadBalancer) -> Vec<String> {
+    load_balancer
+        .endpoints()
+        .iter()
+        .map(|e| e.url.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn reload_swaps_in_the_new_endpoints() {
+    let file = ConfigFile::new(
+        "swap.yaml",
+        "required_model: llama3\nendpoints:\n  - url: http://gpu1:11434\n  - url: http://gpu2:11434\n",
+    );
+    let (reloader, load_balancer) = start(&file);
+
+    file.write(
+        "required_model: llama3\nendpoints:\n  - url: http://gpu2:11434\n    weight: 3\n  - url: http://gpu3:11434\n",
+    );
+    let changes = reloader.reload().unwrap();
+    assert_eq!(changes.added, ["http://gpu3:11434"]);
+    assert_eq!(changes.removed, ["http://gpu1:11434"]);
+    assert_eq!(changes.updated, ["http://gpu2:11434"]);
+    assert_eq!(
+        urls(&load_balancer),
+        ["http://gpu2:11434", "http://gpu3:11434"]
+    );
+
+    assert!(reloader.reload().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn invalid_config_keeps_the_running_endpoints() {
+    let file = ConfigFile::new(
+        "invalid.yaml",
+        "required_model: llama3\nendpoints:\n  - url: http://gpu1:11434\n",
+    );
+    let (reloader, load_balancer) = start(&file);
+
+    file.write("required_model: llama3\nendpoints:\n  - url: http://gpu2:11434\n    weight: 0\n");
+    assert!(reloader.reload().is_err());
+    file.write("required_model: llama3\nendpoints:\n  - url: http://gpu2:11434\n    wieght: 2\n");
+    assert!(reloader.reload().is_err());
+    assert_eq!(urls(&load_balancer), ["http://gpu1:11434"]);
+}
+
+#[tokio::test]
+async fn toml_and_json_configs_reload() {
+    let toml = ConfigFile::new(
+        "config.toml",
+        "required_model = \"llama3\"\n\n[[endpoints]]\nurl = \"http://gpu1:11434\"\n",
+    );
+    let (reloader, _load_balancer) = start(&toml);
+    toml.write(
+        "required_model = \"llama3\"\n\n[[endpoints]]\nurl = \"http://gpu1:11434\"\n\n\
+         [[endpoints]]\nurl = \"http://gpu2:11434\"\n",
+    );
+    assert_eq!(reloader.reload().unwrap().added, ["http://gpu2:11434"]);
+
+    let json = ConfigFile::new(
+        "config.json",
+        r#"{"required_model": "llama3", "endpoints": [{"url": "http://gpu1:11434"}]}"#,
+    );
+    let (reloader, _load_balancer) = start(&json);
+    json.write(r#"{"required_model": "llama3", "endpoints": [{"url": "http://gpu2:11434"}]}"#);
+    let changes = reloader.reload().unwrap();
+    assert_eq!(changes.added, ["http://gpu2:11434"]);
+    assert_eq!(changes.removed, ["http://gpu1:11434"]);
+}
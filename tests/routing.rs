@@ -0,0 +1,160 @@
+mod common;
+
+use ollama_manager::{LoadBalancer, LoadBalancerError, Route};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+fn load_balancer() -> Arc<LoadBalancer> {
+    let config = common::config(
+        r#"
+endpoints:
+  - url: "http://gpu1:11434"
+    labels: { gpu: a100, zone: rack1 }
+  - url: "http://gpu2:11434"
+    labels: { gpu: a100, zone: rack2, tier: spot }
+  - url: "http://cpu1:11434"
+    labels: { zone: rack2 }
+required_model: "test-model"
+"#,
+    );
+    let load_balancer = common::load_balancer(&config);
+    common::mark_healthy(&load_balancer);
+    load_balancer
+}
+
+async fn picks(load_balancer: &LoadBalancer, route: &str) -> HashSet<String> {
+    let route: Route = route.parse().unwrap();
+    let mut urls = HashSet::new();
+    for _ in 0..6 {
+        let endpoint = load_balancer.get_endpoint(None, &route).await.unwrap();
+        urls.insert(endpoint.url);
+    }
+    urls
+}
+
+#[test]
+fn route_headers_are_parsed() {
+    let route: Route = "gpu=a100, tier!=spot, ~zone = rack2".parse().unwrap();
+    assert_eq!(route.required.len(), 2);
+    assert!(route.required[1].negated);
+    assert_eq!(route.preferred[0].key, "zone");
+    assert_eq!(route.to_string(), "gpu=a100, tier!=spot, ~zone=rack2");
+
+    assert!("".parse::<Route>().unwrap().is_empty());
+    assert!("gpu".parse::<Route>().is_err());
+    assert!("=a100".parse::<Route>().is_err());
+}
+
+#[tokio::test]
+async fn required_labels_narrow_the_candidates() {
+    let load_balancer = load_balancer();
+    assert_eq!(
+        picks(&load_balancer, "gpu=a100").await,
+        HashSet::from([
+            "http://gpu1:11434".to_string(),
+            "http://gpu2:11434".to_string()
+        ])
+    );
+    assert_eq!(
+        picks(&load_balancer, "gpu=a100, tier!=spot").await,
+        HashSet::from(["http://gpu1:11434".to_string()])
+    );
+
+    let route: Route = "gpu=h100".parse().unwrap();
+    assert!(matches!(
+        load_balancer.get_endpoint(None, &route).await,
+        Err(LoadBalancerError::NoMatchingEndpoints(_))
+    ));
+
+    // Matching endpoints that are unavailable do not widen the route
+    load_balancer
+        .find_endpoint("http://gpu1:11434")
+        .unwrap()
+        .drain();
+    let route: Route = "tier!=spot, gpu=a100".parse().unwrap();
+    assert!(matches!(
+        load_balancer.get_endpoint(None, &route).await,
+        Err(LoadBalancerError::NoHealthyEndpoints)
+    ));
+}
+
+#[tokio::test]
+async fn preferred_labels_fall_back_when_unavailable() {
+    let load_balancer = load_balancer();
+    assert_eq!(
+        picks(&load_balancer, "gpu=a100, ~zone=rack2").await,
+        HashSet::from(["http://gpu2:11434".to_string()])
+    );
+    assert_eq!(
+        picks(&load_balancer, "~zone=rack2, ~gpu=a100").await,
+        HashSet::from(["http://gpu2:11434".to_string()])
+    );
+
+    load_balancer
+        .find_endpoint("http://gpu2:11434")
+        .unwrap()
+        .drain();
+    assert_eq!(
+        picks(&load_balancer, "gpu=a100, ~zone=rack2").await,
+        HashSet::from(["http://gpu1:11434".to_string()])
+    );
+    assert_eq!(
+        picks(&load_balancer, "~zone=rack2, ~gpu=a100").await,
+        HashSet::from([
+            "http://gpu1:11434".to_string(),
+            "http://cpu1:11434".to_string()
+        ])
+    );
+}
+
+#[tokio::test]
+async fn endpoints_at_max_connections_are_skipped() {
+    let config = common::config(
+        r#"
+endpoints:
+  - url: "http://gpu1:11434"
+    max_connections: 1
+    labels: { zone: rack1 }
+  - url: "http://gpu2:11434"
+    max_connections: 2
+required_model: "test-model"
+"#,
+    );
+    let load_balancer = common::load_balancer(&config);
+    common::mark_healthy(&load_balancer);
+    let gpu1 = load_balancer.find_endpoint("http://gpu1:11434").unwrap();
+    let gpu2 = load_balancer.find_endpoint("http://gpu2:11434").unwrap();
+
+    // A full preferred endpoint falls back like an unavailable one
+    let _first = gpu1.track_connection();
+    assert_eq!(
+        picks(&load_balancer, "~zone=rack1").await,
+        HashSet::from(["http://gpu2:11434".to_string()])
+    );
+
+    let _second = gpu2.track_connection();
+    let _third = gpu2.track_connection();
+    assert!(matches!(
+        load_balancer.get_endpoint(None, &Route::default()).await,
+        Err(LoadBalancerError::AtCapacity)
+    ));
+}
+
+#[tokio::test]
+async fn drained_endpoints_leave_the_rotation_until_undrained() {
+    let load_balancer = load_balancer();
+    let gpu1 = load_balancer.find_endpoint("http://gpu1:11434").unwrap();
+    gpu1.drain();
+    assert_eq!(
+        picks(&load_balancer, "").await,
+        HashSet::from([
+            "http://gpu2:11434".to_string(),
+            "http://cpu1:11434".to_string()
+        ])
+    );
+
+    gpu1.undrain();
+    assert!(picks(&load_balancer, "")
+        .await
+        .contains("http://gpu1:11434"));
+}
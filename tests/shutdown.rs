@@ -0,0 +1,210 @@
+#![cfg(unix)]
+
+mod common;
+
+use axum::{body::Body, response::Response, routing::get, routing::post, Json, Router};
+use bytes::Bytes;
+use futures_util::StreamExt;
+use ollama_manager::server::{self, AppState};
+use reqwest::StatusCode;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const MODEL: &str = "test-model";
+
+/// An Ollama stand-in whose `/api/generate` streams `chunks` tokens 100ms
+/// apart, or never stops if `chunks` is `None`.
+async fn start_upstream(chunks: Option<usize>) -> SocketAddr {
+    let app = Router::new()
+        .route("/", get(|| async { "Ollama is running" }))
+        .route(
+            "/api/tags",
+            get(|| async {
+                Json(serde_json::json!({ "models": [{ "name": MODEL, "model": MODEL }] }))
+            }),
+        )
+        .route(
+            "/api/generate",
+            post(move || async move {
+                let tokens = futures_util::stream::iter(0..)
+                    .take(chunks.unwrap_or(usize::MAX))
+                    .then(move |i| async move {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        let done = chunks == Some(i + 1);
+                        let chunk = format!("{{\"response\":\"{}\",\"done\":{}}}\n", i, done);
+                        Ok::<_, std::io::Error>(Bytes::from(chunk))
+                    });
+                Response::builder()
+                    .header("content-type", "application/x-ndjson")
+                    .body(Body::from_stream(tokens))
+                    .unwrap()
+            }),
+        );
+    common::serve(app).await
+}
+
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+/// The manager binary serving `upstream`, killed when dropped.
+struct Manager {
+    child: Child,
+    listen: SocketAddr,
+    config: PathBuf,
+}
+
+impl Manager {
+    async fn start(upstream: SocketAddr, grace_period_seconds: u64) -> Self {
+        let (listen, admin_listen) = (free_addr(), free_addr());
+        let config = std::env::temp_dir().join(format!(
+            "ollama-manager-shutdown-{}-{}.yaml",
+            std::process::id(),
+            listen.port()
+        ));
+        std::fs::write(
+            &config,
+            format!(
+                r#"
+endpoints:
+  - url: "http://{upstream}"
+server:
+  listen: "{listen}"
+  admin_listen: "{admin_listen}"
+  log_level: warn
+shutdown:
+  grace_period_seconds: {grace_period_seconds}
+required_model: "{MODEL}"
+"#
+            ),
+        )
+        .unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_ollama-manager"))
+            .arg("--config")
+            .arg(&config)
+            .arg("serve")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let manager = Self {
+            child,
+            listen,
+            config,
+        };
+        manager.wait_until_ready().await;
+        manager
+    }
+
+    async fn wait_until_ready(&self) {
+        let url = format!("http://{}/health", self.listen);
+        for _ in 0..100 {
+            if let Ok(response) = reqwest::get(&url).await {
+                if response.status() == StatusCode::OK {
+                    return;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("manager never became ready");
+    }
+
+    fn terminate(&self) {
+        let status = Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    /// Waits for the process to exit, returning whether it succeeded.
+    async fn exit(&mut self, within: Duration) -> bool {
+        let deadline = Instant::now() + within;
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.success();
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("manager still running after {:?}", within);
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.config);
+    }
+}
+
+async fn generate(manager: &Manager) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://{}/api/generate", manager.listen))
+        .json(&serde_json::json!({ "model": MODEL, "prompt": "hi" }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn sigterm_lets_in_flight_streams_finish() {
+    let upstream = start_upstream(Some(8)).await;
+    let mut manager = Manager::start(upstream, 30).await;
+
+    let mut body = generate(&manager).await.bytes_stream();
+    let mut received = body.next().await.unwrap().unwrap().to_vec();
+    manager.terminate();
+    while let Some(chunk) = body.next().await {
+        received.extend_from_slice(&chunk.unwrap());
+    }
+
+    let lines: Vec<&str> = std::str::from_utf8(&received).unwrap().lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert!(lines[7].contains("\"done\":true"));
+    assert!(manager.exit(Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn sigterm_gives_up_on_streams_after_the_grace_period() {
+    let upstream = start_upstream(None).await;
+    let mut manager = Manager::start(upstream, 1).await;
+
+    let mut body = generate(&manager).await.bytes_stream();
+    body.next().await.unwrap().unwrap();
+    let signalled = Instant::now();
+    manager.terminate();
+
+    assert!(manager.exit(Duration::from_secs(5)).await);
+    assert!(signalled.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn health_fails_once_shutdown_begins() {
+    let config = common::config(
+        r#"
+endpoints:
+  - url: "http://gpu1:11434"
+required_model: "test-model"
+"#,
+    );
+    let state = Arc::new(AppState::new(
+        common::load_balancer(&config),
+        config.required_model.clone(),
+    ));
+    let proxy = common::serve(server::router(state.clone())).await;
+
+    state.begin_shutdown();
+    let response = reqwest::get(format!("http://{}/health", proxy))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let health: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(health["status"], "SHUTTING_DOWN");
+}
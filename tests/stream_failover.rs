@@ -0,0 +1,180 @@
+mod common;
+
+use axum::{
+    http::{header, StatusCode},
+    routing::get,
+    routing::post,
+    Json, Router,
+};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const MODEL: &str = "test-model";
+const NDJSON: &str = "application/x-ndjson";
+
+/// How an upstream answers a chat request, and how it answers the
+/// continuation of one that failed over from another endpoint.
+#[derive(Clone, Copy)]
+struct Answers {
+    first: &'static str,
+    continuation: (StatusCode, &'static str, &'static str),
+}
+
+type Requests = Arc<Mutex<Vec<Value>>>;
+
+/// An Ollama stand-in that answers `/api/chat` as told, keeping the requests.
+async fn start_upstream(answers: Answers, requests: Requests) -> SocketAddr {
+    let app = Router::new()
+        .route(
+            "/api/tags",
+            get(|| async {
+                Json(serde_json::json!({ "models": [{ "name": MODEL, "model": MODEL }] }))
+            }),
+        )
+        .route(
+            "/api/chat",
+            post(move |Json(request): Json<Value>| async move {
+                let continuing = request["messages"]
+                    .as_array()
+                    .is_some_and(|messages| messages.iter().any(|m| m["role"] == "assistant"));
+                requests.lock().unwrap().push(request);
+                let (status, content_type, body) = if continuing {
+                    answers.continuation
+                } else {
+                    (StatusCode::OK, NDJSON, answers.first)
+                };
+                (status, [(header::CONTENT_TYPE, content_type)], body)
+            }),
+        );
+    common::serve(app).await
+}
+
+/// Streams a chat answer through the proxy from two endpoints answering
+/// alike, returning the chunks the client received and the upstream requests.
+async fn chat(answers: Answers) -> (Vec<Value>, Vec<Value>) {
+    let requests = Requests::default();
+    let first = start_upstream(answers, requests.clone()).await;
+    let second = start_upstream(answers, requests.clone()).await;
+    let config = common::config(&format!(
+        r#"
+endpoints:
+  - url: "http://{first}"
+  - url: "http://{second}"
+stream_failover:
+  enabled: true
+retry:
+  max_attempts: 1
+required_model: "{MODEL}"
+"#
+    ));
+    let (proxy, _load_balancer) = common::start_proxy(&config).await;
+
+    let body = reqwest::Client::new()
+        .post(format!("http://{}/api/chat", proxy))
+        .json(&serde_json::json!({
+            "model": MODEL,
+            "messages": [{ "role": "user", "content": "hi" }],
+        }))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.ends_with('\n'), "{}", body);
+    let chunks = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let requests = requests.lock().unwrap().clone();
+    (chunks, requests)
+}
+
+fn content(chunks: &[Value]) -> String {
+    chunks
+        .iter()
+        .filter_map(|chunk| chunk["message"]["content"].as_str())
+        .collect()
+}
+
+const TRUNCATED: &str = "{\"message\":{\"content\":\"Hel\"},\"done\":false}\n";
+
+#[tokio::test]
+async fn final_chunk_without_newline_completes_the_stream() {
+    let (chunks, requests) = chat(Answers {
+        first: "{\"message\":{\"content\":\"Hel\"},\"done\":false}\n\
+                {\"message\":{\"content\":\"lo\"},\"done\":true}",
+        continuation: (StatusCode::OK, NDJSON, ""),
+    })
+    .await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(content(&chunks), "Hello");
+    assert_eq!(chunks.last().unwrap()["done"], true);
+}
+
+#[tokio::test]
+async fn truncated_stream_continues_on_another_endpoint() {
+    let (chunks, requests) = chat(Answers {
+        first: TRUNCATED,
+        continuation: (
+            StatusCode::OK,
+            NDJSON,
+            "{\"message\":{\"content\":\"lo\"},\"done\":false}\n\
+             {\"message\":{\"content\":\"\"},\"done\":true}\n",
+        ),
+    })
+    .await;
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[1]["messages"],
+        serde_json::json!([
+            { "role": "user", "content": "hi" },
+            { "role": "assistant", "content": "Hel" },
+        ])
+    );
+    assert_eq!(content(&chunks), "Hello");
+    assert_eq!(chunks[1]["failover"], serde_json::json!({ "count": 1 }));
+    assert_eq!(
+        chunks.iter().filter(|c| !c["failover"].is_null()).count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn failed_continuation_is_not_spliced_in() {
+    let (chunks, requests) = chat(Answers {
+        first: TRUNCATED,
+        continuation: (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "application/json",
+            "{\"error\":\"model crashed\"}",
+        ),
+    })
+    .await;
+    assert_eq!(requests.len(), 2);
+    assert_eq!(content(&chunks), "Hel");
+    let error = chunks.last().unwrap()["error"].as_str().unwrap();
+    assert!(error.contains("returned 500"), "{}", error);
+}
+
+#[tokio::test]
+async fn continuation_must_be_a_stream() {
+    let (chunks, requests) = chat(Answers {
+        first: TRUNCATED,
+        continuation: (
+            StatusCode::OK,
+            "application/json",
+            "{\"message\":{\"content\":\"lo\"},\"done\":true}",
+        ),
+    })
+    .await;
+    assert_eq!(requests.len(), 2);
+    assert_eq!(content(&chunks), "Hel");
+    let error = chunks.last().unwrap()["error"].as_str().unwrap();
+    assert!(
+        error.contains("did not return an NDJSON stream"),
+        "{}",
+        error
+    );
+}